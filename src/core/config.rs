@@ -1,12 +1,18 @@
 use std::collections::HashMap;
+use std::fs::Metadata;
+use std::os::unix::fs::MetadataExt;
+use std::time::SystemTime;
 
 use colored::Colorize;
 use serde::{Deserialize, Serialize};
 
 use crate::{debug_fn, debug_info};
 use crate::core::error::HinaError;
+use crate::core::func;
 use crate::core::global::{MODULE_MAP, DEBUG};
 use crate::event::fs::{LinkConvert, MakeNestedDir, Rename};
+use crate::event::base::HinaModuleRun;
+use crate::event::completion::Completion;
 use crate::event::holder::PlaceHold;
 use crate::event::process::Process;
 use crate::event::recycle::{RecycleBin, Remove};
@@ -19,12 +25,60 @@ pub enum Module {
     Process(Process),
     Rename(Rename),
     LinkConvert(LinkConvert),
+    Completion(Completion),
     None(PlaceHold),
 }
 
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum TrashBackend {
+    Hina,
+    Xdg,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum BinStore {
+    Plain,
+    Dedup,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Compression {
+    None,
+    Zstd,
+    Gzip,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum FlagType {
+    Bool,
+    Str,
+    Uint,
+    // Values that may be left out, like --last or --last=3
+    OptStr,
+    OptUint,
+}
+
+#[derive(Debug, Clone)]
+pub struct FlagSpec {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub kind: FlagType,
+    // Placeholder of the value in help, like N or DIR
+    pub value: &'static str,
+    pub default: &'static str,
+    pub choices: &'static [&'static str],
+    // Completion of the value, or of the args for a flag without value: files, dirs, or
+    // records and pids which are listed by hina completions --list
+    pub complete: &'static str,
+    // Flags cancelled when this one is given after them, like -f after -i in rm
+    pub overrides: &'static [&'static str],
+    pub help: &'static str,
+}
+
 #[derive(Debug, Clone)]
 pub struct Flag {
     flags: HashMap<String, String>,
+    defaults: HashMap<String, String>,
 }
 
 #[derive(Debug, Clone)]
@@ -39,6 +93,26 @@ pub struct RMRecord {
     file: String,
     src: String,
     delete_time: String,
+    #[serde(default)]
+    bin: String,
+    #[serde(default)]
+    size: Option<u64>,
+    #[serde(default)]
+    file_type: String,
+    #[serde(default)]
+    link_target: Option<String>,
+    #[serde(default)]
+    mode: Option<u32>,
+    #[serde(default)]
+    uid: Option<u32>,
+    #[serde(default)]
+    gid: Option<u32>,
+    #[serde(default)]
+    mtime: Option<SystemTime>,
+    #[serde(default)]
+    batch: String,
+    #[serde(default)]
+    blob: Option<String>,
 }
 
 impl Flag {
@@ -54,38 +128,124 @@ impl Flag {
 
     pub fn parse_string(&self, symbols: Vec<&str>) -> String {
         debug_fn!(symbols);
+        for symbol in symbols.iter() {
+            if self.flags.contains_key(*symbol) {
+                return self.flags[*symbol].clone();
+            }
+        }
         for symbol in symbols {
-            if self.flags.contains_key(symbol) {
-                return self.flags[symbol].clone();
+            if self.defaults.contains_key(symbol) {
+                return self.defaults[symbol].clone();
             }
         }
         return String::new();
     }
 
     pub fn parse_uint(&self, symbols: Vec<&str>) -> usize {
+        // Values are checked while parsing, so only a bare optional value is left as 0
         debug_fn!(symbols);
-        for symbol in symbols {
-            if self.flags.contains_key(symbol) {
-                return self.flags[symbol].clone().parse().unwrap_or(0);
-            }
-        }
-        return 0;
+        return self.parse_string(symbols).parse().unwrap_or(0);
     }
 }
 
-impl Config {
-    pub fn add_flag(input: &String, index: usize, map: &mut HashMap<String, String>) {
-        debug_fn!(input,map,index);
-        if input.contains("=") {
-            let entries: Vec<&str> = input.split("=").collect();
-            map.insert(entries[0][index..].to_string(), entries[1].to_string());
+impl FlagSpec {
+    pub const fn new(name: &'static str,
+                     aliases: &'static [&'static str],
+                     kind: FlagType,
+                     value: &'static str,
+                     help: &'static str) -> FlagSpec {
+        FlagSpec { name, aliases, kind, value, default: "", choices: &[], complete: "", overrides: &[], help }
+    }
+
+    pub fn get_names(&self) -> Vec<String> {
+        // Single letters and aliases are written with one dash, like -f and -ls, long names with two
+        debug_fn!();
+        let mut names: Vec<String> = self.aliases.iter().map(|alias| format!("-{}", alias)).collect();
+        if self.name.len() == 1 {
+            names.insert(0, format!("-{}", self.name));
         } else {
-            map.insert(input[index..].to_string(), "".to_string());
+            names.push(format!("--{}", self.name));
         }
+        names
     }
 
+    pub fn get_synopsis(&self) -> String {
+        // Names with the value placeholder, like -i, --input=PATTERN or --last[=N]
+        debug_fn!();
+        let names = self.get_names().join(", ");
+        match self.kind {
+            FlagType::Bool => { names }
+            FlagType::Str | FlagType::Uint => { format!("{}={}", names, self.value) }
+            FlagType::OptStr | FlagType::OptUint => { format!("{}[={}]", names, self.value) }
+        }
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        debug_fn!(name);
+        self.name == name || self.aliases.contains(&name)
+    }
+
+    fn check_value(&self, flag: &str, value: &str) -> Result<(), HinaError> {
+        debug_fn!(flag,value);
+        if value.is_empty() {
+            return Ok(());
+        }
+        if matches!(self.kind, FlagType::Uint | FlagType::OptUint) && value.parse::<usize>().is_err() {
+            let err = format!("Flag \'{}\' expects a non-negative integer, got \'{}\'", flag, value);
+            return Err(HinaError::ConfigParseError(err));
+        }
+        if !self.choices.is_empty() && !self.choices.contains(&value) {
+            let err = format!("Flag \'{}\' expects one of {}, got \'{}\'", flag, self.choices.join(", "), value);
+            return Err(HinaError::ConfigParseError(err));
+        }
+        Ok(())
+    }
+}
+
+impl Module {
+    pub fn get_name(&self) -> &str {
+        debug_fn!();
+        MODULE_MAP
+            .iter()
+            .find(|(_, module)| *module == self)
+            .map(|(name, _)| *name)
+            .unwrap_or("hina")
+    }
+
+    pub fn get_flag_specs(&self) -> Vec<FlagSpec> {
+        // Every module gets --help, and -h as well unless the module uses it for something else
+        debug_fn!();
+        let mut specs = match self {
+            Module::Remove(module) => { module.flag_specs() }
+            Module::RecycleBin(module) => { module.flag_specs() }
+            Module::MakeNestedDir(module) => { module.flag_specs() }
+            Module::Process(module) => { module.flag_specs() }
+            Module::Rename(module) => { module.flag_specs() }
+            Module::LinkConvert(module) => { module.flag_specs() }
+            Module::Completion(module) => { module.flag_specs() }
+            Module::None(module) => { module.flag_specs() }
+        };
+        if !specs.iter().any(|spec| spec.matches("help")) {
+            let aliases: &'static [&'static str] = if specs.iter().any(|spec| spec.matches("h")) { &[] } else { &["h"] };
+            specs.push(FlagSpec {
+                choices: &["man"],
+                ..FlagSpec::new("help", aliases, FlagType::OptStr, "man", "Show this help, or the man page with --help=man.")
+            });
+        }
+        specs
+    }
+}
+
+impl Config {
     pub fn build(input: &[String]) -> Result<Config, HinaError> {
         debug_fn!(input);
+        // hina help [<module>] is the same as hina [<module>] --help
+        if input.len() >= 2 && input[1] == "help" {
+            let mut rewritten = vec![input[0].clone()];
+            rewritten.extend_from_slice(&input[2..]);
+            rewritten.push(String::from("--help"));
+            return Config::build(&rewritten);
+        }
         let target;
         let index;
         let need_parse;
@@ -124,11 +284,21 @@ impl Config {
             }
         }
 
-        let (flags, args) = Config::parse_flag_and_arg(&mut args_or_flags);
+        let specs = target.get_flag_specs();
+        let (mut flags, args) = Config::parse_flag_and_arg(&specs, target.get_name(), &args_or_flags)?;
+        if input.len() >= 2 && MODULE_MAP.contains_key(input[1].as_str()) {
+            let defaults = func::get_settings()?.get_flags(&input[1]);
+            Config::merge_default_flags(&specs, &input[1], &mut flags, &defaults)?;
+        }
+        let defaults = specs
+            .iter()
+            .filter(|spec| !spec.default.is_empty())
+            .map(|spec| (spec.name.to_string(), spec.default.to_string()))
+            .collect();
         let config = Config {
             module: target,
             args,
-            flags: Flag { flags },
+            flags: Flag { flags, defaults },
         };
         return Ok(config);
     }
@@ -148,35 +318,234 @@ impl Config {
         return &self.flags;
     }
 
-    fn parse_flag_and_arg(input: &mut Vec<String>) -> (HashMap<String, String>, Vec<String>) {
-        debug_fn!();
+    pub fn merge_default_flags(specs: &[FlagSpec],
+                               module_name: &str,
+                               flags: &mut HashMap<String, String>,
+                               defaults: &Vec<String>) -> Result<(), HinaError> {
+        // Default flags from the config file only fill in what the command line left out
+        debug_fn!(module_name,flags,defaults);
+        let (default_flags, _) = match Config::parse_flag_and_arg(specs, module_name, defaults) {
+            Ok(parsed) => { parsed }
+            Err(HinaError::ConfigParseError(err)) => {
+                return Err(HinaError::ConfigParseError(format!("Default flags of {} in config: {}", module_name, err)));
+            }
+            Err(err) => { return Err(err); }
+        };
+        for (key, value) in default_flags {
+            // A default overridden on the command line is dropped, like a default -i by -f
+            let overridden = specs
+                .iter()
+                .find(|spec| spec.name == key)
+                .is_some_and(|spec| spec.overrides.iter().any(|name| flags.contains_key(*name)));
+            if !overridden {
+                flags.entry(key).or_insert(value);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn parse_flag_and_arg(specs: &[FlagSpec],
+                              module_name: &str,
+                              input: &Vec<String>) -> Result<(HashMap<String, String>, Vec<String>), HinaError> {
+        // Flags are looked up by name or alias with one or two dashes, values are given as --flag=value
+        // or --flag value, optional numbers as --flag=N or --flag N, optional strings only as
+        // --flag=value, single letter flags can be combined like -rf, and -- ends the flags
+        debug_fn!(module_name,input);
         let mut flags: HashMap<String, String> = HashMap::new();
         let mut args = Vec::new();
-        let _: Vec<_> = input.iter().map(
-            |x| {
-                if x.starts_with("--") {
-                    Config::add_flag(x, 2, &mut flags)
-                } else if x.starts_with("-") {
-                    Config::add_flag(x, 1, &mut flags)
-                } else {
-                    args.push(x.clone())
+        let mut only_args = false;
+        let mut i = 0;
+        while i < input.len() {
+            let entry = &input[i];
+            i += 1;
+            if only_args || !entry.starts_with('-') || entry == "-" {
+                args.push(entry.clone());
+                continue;
+            }
+            if entry == "--" {
+                only_args = true;
+                continue;
+            }
+            let body = entry.strip_prefix("--").unwrap_or(&entry[1..]);
+            let (name, value) = match body.split_once('=') {
+                Some((name, value)) => { (name, Some(value)) }
+                None => { (body, None) }
+            };
+            if let Some(spec) = specs.iter().find(|spec| spec.matches(name)) {
+                let value = match (spec.kind, value) {
+                    (FlagType::Bool, Some(_)) => {
+                        return Err(HinaError::ConfigParseError(format!("Flag \'{}\' takes no value", entry)));
+                    }
+                    (FlagType::Str | FlagType::Uint, None) => {
+                        if i >= input.len() {
+                            return Err(HinaError::ConfigParseError(format!("Flag \'{}\' expects a value", entry)));
+                        }
+                        i += 1;
+                        input[i - 1].clone()
+                    }
+                    (FlagType::OptUint, None) if input.get(i).is_some_and(|next| next.parse::<usize>().is_ok()) => {
+                        // Like --last 3, an optional number may follow as the next token
+                        i += 1;
+                        input[i - 1].clone()
+                    }
+                    (_, value) => { value.unwrap_or_default().to_string() }
+                };
+                spec.check_value(entry, &value)?;
+                Config::set_flag(&mut flags, spec, value);
+                continue;
+            }
+            // Combined single letter flags, only those without a value
+            let combined: Vec<(char, Option<&FlagSpec>)> = name
+                .chars()
+                .map(|c| (c, specs.iter().find(|spec| spec.matches(&c.to_string()))))
+                .collect();
+            if entry.starts_with("--") || value.is_some() || combined.len() < 2 || combined.iter().any(|(_, spec)| spec.is_none()) {
+                return Err(HinaError::ConfigParseError(Config::unknown_flag(specs, module_name, entry, name)));
+            }
+            for (c, spec) in combined {
+                let spec = spec.unwrap();
+                if spec.kind != FlagType::Bool {
+                    let err = format!("Flag \'-{}\' expects a value and can not be combined in \'{}\'", c, entry);
+                    return Err(HinaError::ConfigParseError(err));
                 }
+                Config::set_flag(&mut flags, spec, String::new());
             }
-        ).collect();
-        return (flags, args);
+        }
+        return Ok((flags, args));
+    }
+
+    fn set_flag(flags: &mut HashMap<String, String>, spec: &FlagSpec, value: String) {
+        // The last of conflicting flags wins
+        debug_fn!(value);
+        for name in spec.overrides {
+            flags.remove(*name);
+        }
+        flags.insert(spec.name.to_string(), value);
+    }
+
+    fn unknown_flag(specs: &[FlagSpec], module_name: &str, entry: &str, name: &str) -> String {
+        // Suggest the closest flag name when there is one within two edits
+        debug_fn!(module_name,entry,name);
+        let suggestion = specs
+            .iter()
+            .flat_map(|spec| spec.get_names())
+            .map(|candidate| (func::edit_distance(name, candidate.trim_start_matches('-')), candidate))
+            .filter(|(distance, _)| *distance <= 2)
+            .min_by_key(|(distance, _)| *distance);
+        match suggestion {
+            Some((_, candidate)) => { format!("Unknown flag \'{}\' for {}, did you mean \'{}\'?", entry, module_name, candidate) }
+            None => { format!("Unknown flag \'{}\' for {}, see \'hina {} --help\'", entry, module_name, module_name) }
+        }
+    }
+}
+
+impl TrashBackend {
+    pub fn from(name: &str) -> Result<TrashBackend, HinaError> {
+        debug_fn!(name);
+        match name {
+            "" | "hina" => { Ok(TrashBackend::Hina) }
+            "xdg" => { Ok(TrashBackend::Xdg) }
+            _ => {
+                let err = format!("Unknown trash backend \'{}\', expect hina or xdg", name);
+                Err(HinaError::ConfigParseError(err))
+            }
+        }
+    }
+}
+
+impl BinStore {
+    pub fn from(name: &str) -> Result<BinStore, HinaError> {
+        debug_fn!(name);
+        match name {
+            "" | "plain" => { Ok(BinStore::Plain) }
+            "dedup" => { Ok(BinStore::Dedup) }
+            _ => {
+                let err = format!("Unknown bin store \'{}\', expect plain or dedup", name);
+                Err(HinaError::ConfigParseError(err))
+            }
+        }
+    }
+}
+
+impl Compression {
+    pub fn from(name: &str) -> Result<Compression, HinaError> {
+        debug_fn!(name);
+        match name {
+            "" | "none" => { Ok(Compression::None) }
+            "zstd" => { Ok(Compression::Zstd) }
+            "gzip" => { Ok(Compression::Gzip) }
+            _ => {
+                let err = format!("Unknown compression \'{}\', expect none, zstd or gzip", name);
+                Err(HinaError::ConfigParseError(err))
+            }
+        }
     }
 }
 
 impl RMRecord {
     pub fn from(file: String,
                 src: String,
-                delete_time: String) -> RMRecord {
+                delete_time: String,
+                bin: String) -> RMRecord {
         debug_fn!();
         return RMRecord {
             file,
             src,
             delete_time,
+            bin,
+            size: None,
+            file_type: String::new(),
+            link_target: None,
+            mode: None,
+            uid: None,
+            gid: None,
+            mtime: None,
+            batch: String::new(),
+            blob: None,
+        };
+    }
+
+    pub fn set_meta(&mut self, meta: &Metadata, size: u64, link_target: Option<String>) {
+        // Remember what the removed target looked like, taken before it is moved
+        debug_fn!(size,link_target);
+        let file_type = meta.file_type();
+        self.file_type = if file_type.is_symlink() {
+            String::from("symlink")
+        } else if file_type.is_dir() {
+            String::from("dir")
+        } else if file_type.is_file() {
+            String::from("file")
+        } else {
+            String::from("other")
         };
+        self.size = Some(size);
+        self.link_target = link_target;
+        self.mode = Some(meta.mode());
+        self.uid = Some(meta.uid());
+        self.gid = Some(meta.gid());
+        self.mtime = meta.modified().ok();
+    }
+
+    pub fn set_batch(&mut self, batch: String) {
+        debug_fn!(batch);
+        self.batch = batch;
+    }
+
+    pub fn set_blob(&mut self, blob: Option<String>) {
+        debug_fn!(blob);
+        self.blob = blob;
+    }
+
+    pub fn get_blob(&self) -> &Option<String> {
+        // Hash of the payload in the dedup store, None when the file is kept in a bin as is
+        debug_fn!();
+        return &self.blob;
+    }
+
+    pub fn get_batch(&self) -> &String {
+        // Empty for records written before batches were recorded
+        debug_fn!();
+        return &self.batch;
     }
 
     pub fn get_file(&self) -> &String {
@@ -193,4 +562,46 @@ impl RMRecord {
         debug_fn!();
         return &self.delete_time;
     }
+
+    pub fn get_size(&self) -> Option<u64> {
+        debug_fn!();
+        return self.size;
+    }
+
+    pub fn get_file_type(&self) -> &String {
+        // Empty for records written before file types were recorded
+        debug_fn!();
+        return &self.file_type;
+    }
+
+    pub fn get_link_target(&self) -> &Option<String> {
+        debug_fn!();
+        return &self.link_target;
+    }
+
+    pub fn get_mode(&self) -> Option<u32> {
+        debug_fn!();
+        return self.mode;
+    }
+
+    pub fn get_uid(&self) -> Option<u32> {
+        debug_fn!();
+        return self.uid;
+    }
+
+    pub fn get_gid(&self) -> Option<u32> {
+        debug_fn!();
+        return self.gid;
+    }
+
+    pub fn get_mtime(&self) -> Option<SystemTime> {
+        debug_fn!();
+        return self.mtime;
+    }
+
+    pub fn get_bin(&self) -> &String {
+        // Records written before per-filesystem bins have no bin, they all live in the home bin
+        debug_fn!();
+        return &self.bin;
+    }
 }
\ No newline at end of file
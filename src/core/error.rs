@@ -16,4 +16,15 @@ pub enum HinaError {
     FileExistError(String),
     DirNotEmptyError(String),
     DirReadError(String),
+    FileMoveError(String),
+    FileCopyError(String),
+    FileRemoveError(String),
+    PatternParseError(String),
+    InputReadError(String),
+    FileLockError(String),
+    StackCorruptError(String),
+    ProtectedPathError(String),
+    BatchError(String),
+    ProcessNotFoundError(String),
+    PartialMoveError(String),
 }
\ No newline at end of file
@@ -1,12 +1,14 @@
+use std::fs;
 use std::path::PathBuf;
 
 use colored::Colorize;
 
 use crate::{debug_fn, debug_info};
-use crate::core::config::{Config, Flag, Module, RMRecord};
+use crate::core::config::{BinStore, Compression, Config, Flag, Module, RMRecord, TrashBackend};
 use crate::core::error::HinaError;
-use crate::core::func;
-use crate::core::global::{DATA_DIR, DEBUG, RECYCLE};
+use crate::core::error::HinaError::DirCreateError;
+use crate::core::{func, xdg};
+use crate::core::global::{DATA_DIR, DEBUG, RECYCLE, TRASH_BACKEND};
 use crate::event::base::HinaModuleRun;
 
 #[derive(Debug, Clone)]
@@ -27,8 +29,33 @@ impl Executor {
         let home_path = PathBuf::from(home_path_str);
         let mut data_path = home_path;
         data_path.push(DATA_DIR);
-        let mut recycle_path = data_path.clone();
-        recycle_path.push(RECYCLE);
+        let settings = func::get_settings()?;
+        let recycle_path = match settings.get_bin_path()? {
+            Some(path) => { path }
+            None => {
+                let mut path = data_path.clone();
+                path.push(RECYCLE);
+                path
+            }
+        };
+        match settings.output.color.as_str() {
+            "always" => { colored::control::set_override(true); }
+            "never" => { colored::control::set_override(false); }
+            // auto, colored itself checks the terminal, NO_COLOR and CLICOLOR
+            _ => {}
+        }
+        // Surface mistyped settings instead of silently using the defaults
+        TrashBackend::from(&func::get_trash_backend_name())?;
+        let quota = func::get_bin_quota_str();
+        if !quota.is_empty() {
+            func::parse_size(&quota)?;
+        }
+        BinStore::from(&func::get_bin_store_name())?;
+        Compression::from(&func::get_bin_compress_name())?;
+        let compress_after = func::get_bin_compress_after_str();
+        if !compress_after.is_empty() {
+            func::parse_duration(&compress_after)?;
+        }
 
         Ok(Executor {
             config,
@@ -50,6 +77,19 @@ impl Executor {
                 _flags: &Flag,
                 _rm_stack: &mut Vec<RMRecord>,
                 args: &Vec<String>) -> Result<(), HinaError> {
+        let handled = module.run_batch(
+            _work_path,
+            _data_path,
+            _recycle_path,
+            _user,
+            _uid,
+            _flags,
+            _rm_stack,
+            args,
+        )?;
+        if handled {
+            return Ok(());
+        }
         if args.len() > 0 {
             for arg in args {
                 module.run(
@@ -81,37 +121,59 @@ impl Executor {
     pub fn run(&self) -> Result<(), HinaError> {
         debug_fn!();
         func::init_data_dir(&self.data_path)?;
+        // A bin location set in the config file is created on first use
+        if fs::symlink_metadata(&self.recycle_path).is_err() {
+            match fs::create_dir_all(&self.recycle_path) {
+                Ok(_) => {}
+                Err(err) => { return Err(DirCreateError(format!("Unable to create {}: {}", self.recycle_path.display(), err))); }
+            }
+        }
 
         let args = self.config.get_args();
         let flags = self.config.get_flags();
-        let mut rm_stack = func::load_rm_stack(&self.data_path)?;
+        // Only the recycle bin modules touch the stack, others neither wait for the lock nor rewrite it
+        let use_stack = matches!(self.config.get_target(), Module::Remove(_) | Module::RecycleBin(_));
+        let _lock = if use_stack { Some(func::lock_data_dir(&self.data_path)?) } else { None };
+        let mut rm_stack = match (use_stack, *TRASH_BACKEND) {
+            (false, _) => { Vec::new() }
+            (true, TrashBackend::Hina) => { func::load_rm_stack(&self.data_path)? }
+            (true, TrashBackend::Xdg) => { xdg::load_records(&self.uid)? }
+        };
 
-        match self.config.get_target() {
+        // Save whatever succeeded even when the module fails half way
+        let result = match self.config.get_target() {
             Module::Remove(module) => {
-                self.run_iter(module, &self.work_path, &self.data_path, &self.recycle_path, &self.user, &self.uid, flags, &mut rm_stack, args)?
+                self.run_iter(module, &self.work_path, &self.data_path, &self.recycle_path, &self.user, &self.uid, flags, &mut rm_stack, args)
             }
             Module::RecycleBin(module) => {
-                self.run_iter(module, &self.work_path, &self.data_path, &self.recycle_path, &self.user, &self.uid, flags, &mut rm_stack, args)?
+                self.run_iter(module, &self.work_path, &self.data_path, &self.recycle_path, &self.user, &self.uid, flags, &mut rm_stack, args)
             }
             Module::MakeNestedDir(module) => {
-                self.run_iter(module, &self.work_path, &self.data_path, &self.recycle_path, &self.user, &self.uid, flags, &mut rm_stack, args)?
+                self.run_iter(module, &self.work_path, &self.data_path, &self.recycle_path, &self.user, &self.uid, flags, &mut rm_stack, args)
             }
             Module::Process(module) => {
-                self.run_iter(module, &self.work_path, &self.data_path, &self.recycle_path, &self.user, &self.uid, flags, &mut rm_stack, args)?
+                self.run_iter(module, &self.work_path, &self.data_path, &self.recycle_path, &self.user, &self.uid, flags, &mut rm_stack, args)
             }
             Module::Rename(module) => {
-                self.run_iter(module, &self.work_path, &self.data_path, &self.recycle_path, &self.user, &self.uid, flags, &mut rm_stack, args)?
+                self.run_iter(module, &self.work_path, &self.data_path, &self.recycle_path, &self.user, &self.uid, flags, &mut rm_stack, args)
             }
             Module::LinkConvert(module) => {
-                self.run_iter(module, &self.work_path, &self.data_path, &self.recycle_path, &self.user, &self.uid, flags, &mut rm_stack, args)?
+                self.run_iter(module, &self.work_path, &self.data_path, &self.recycle_path, &self.user, &self.uid, flags, &mut rm_stack, args)
+            }
+            Module::Completion(module) => {
+                self.run_iter(module, &self.work_path, &self.data_path, &self.recycle_path, &self.user, &self.uid, flags, &mut rm_stack, args)
             }
 
             Module::None(module) => {
-                self.run_iter(module, &self.work_path, &self.data_path, &self.recycle_path, &self.user, &self.uid, flags, &mut rm_stack, args)?
+                self.run_iter(module, &self.work_path, &self.data_path, &self.recycle_path, &self.user, &self.uid, flags, &mut rm_stack, args)
             }
-        }
+        };
 
-        func::save_rm_stack(&self.data_path, &rm_stack)?;
-        Ok(())
+        match (use_stack, *TRASH_BACKEND) {
+            (false, _) => {}
+            (true, TrashBackend::Hina) => { func::save_rm_stack(&self.data_path, &rm_stack)? }
+            (true, TrashBackend::Xdg) => { xdg::sync_records(&self.uid, &rm_stack)? }
+        }
+        result
     }
 }
\ No newline at end of file
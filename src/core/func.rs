@@ -1,21 +1,26 @@
-use std::cmp::max;
+use std::cmp::{max, min};
 use std::env;
 use std::fs;
-use std::fs::{File, OpenOptions};
-use std::io::{BufReader, BufWriter};
-use std::path::PathBuf;
+use std::fs::{File, FileTimes, Metadata, OpenOptions, Permissions, TryLockError};
+use std::io::{BufWriter, ErrorKind, Seek, SeekFrom, stdin, stdout, Write};
+use std::os::unix::fs::{lchown, MetadataExt, PermissionsExt, symlink};
+use std::path::{Component, Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::time::SystemTime;
 
 use colored::Colorize;
 use execute::{Execute, shell};
+use glob::{MatchOptions, Pattern};
 use rand::{Rng, thread_rng};
 use rand::distributions::Alphanumeric;
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime};
 
 use crate::{debug_fn, debug_info, debugln};
-use crate::core::config::RMRecord;
+use crate::core::config::{Flag, RMRecord};
 use crate::core::error::HinaError;
-use crate::core::error::HinaError::{DirCreateError, FileCreateError, FileOpenError, FileWriteError};
-use crate::core::global::{DEBUG, RECYCLE, RM_STACK};
+use crate::core::error::HinaError::{ConfigParseError, DirCreateError, DirReadError, FileCopyError, FileCreateError, FileLockError, FileMoveError, FileOpenError, FileRemoveError, FileWriteError, InputReadError, PartialMoveError, PatternParseError, StackCorruptError};
+use crate::core::global::{DEBUG, HELP_DICT, LOCK_FILE, MODULE_MAP, MOUNT_INFO, RECYCLE, RM_STACK, SETTINGS, SHRED_BLOCK_SIZE, TIME_FORMAT};
+use crate::core::settings::Settings;
 
 fn read_var(var_name: &str) -> Result<String, HinaError> {
     // Read variable from system variables
@@ -35,6 +40,68 @@ pub fn get_home() -> Result<String, HinaError> {
     Ok(read_var("HOME")?)
 }
 
+pub fn get_xdg_data_home() -> Result<PathBuf, HinaError> {
+    // $XDG_DATA_HOME, default to $HOME/.local/share
+    debug_fn!();
+    match read_var("XDG_DATA_HOME") {
+        Ok(path) if !path.is_empty() => { Ok(PathBuf::from(path)) }
+        _ => {
+            let mut path = PathBuf::from(get_home()?);
+            path.push(".local");
+            path.push("share");
+            Ok(path)
+        }
+    }
+}
+
+fn read_setting(var_name: &str, setting: fn(&Settings) -> &String) -> String {
+    // The environment variable when set, otherwise the value from the config file
+    debug_fn!(var_name);
+    match read_var(var_name) {
+        Ok(val) => { val }
+        Err(_) => { get_settings().map(|settings| setting(settings).clone()).unwrap_or_default() }
+    }
+}
+
+pub fn get_settings() -> Result<&'static Settings, HinaError> {
+    // Settings are loaded once, a broken config file is reported by every caller
+    debug_fn!();
+    match SETTINGS.as_ref() {
+        Ok(settings) => { Ok(settings) }
+        Err(err) => { Err(ConfigParseError(err.clone())) }
+    }
+}
+
+pub fn get_config_path_str() -> String {
+    debug_fn!();
+    read_var("HINA_CONFIG").unwrap_or_default()
+}
+
+pub fn get_trash_backend_name() -> String {
+    debug_fn!();
+    read_setting("HINA_TRASH_BACKEND", |settings| &settings.bin.backend)
+}
+
+pub fn get_bin_quota_str() -> String {
+    debug_fn!();
+    read_setting("HINA_BIN_QUOTA", |settings| &settings.bin.quota)
+}
+
+pub fn get_bin_store_name() -> String {
+    debug_fn!();
+    read_setting("HINA_BIN_STORE", |settings| &settings.bin.store)
+}
+
+pub fn get_bin_compress_name() -> String {
+    debug_fn!();
+    read_setting("HINA_BIN_COMPRESS", |settings| &settings.bin.compress)
+}
+
+pub fn get_bin_compress_after_str() -> String {
+    debug_fn!();
+    read_setting("HINA_BIN_COMPRESS_AFTER", |settings| &settings.bin.compress_after)
+}
+
 pub fn get_user() -> Result<String, HinaError> {
     debug_fn!();
     Ok(read_var("USER")?)
@@ -52,8 +119,16 @@ pub fn get_current_path() -> Result<PathBuf, HinaError> {
 }
 
 pub fn get_uid() -> Result<String, HinaError> {
-    // Use unix shell "id" to get user id
+    // Read the real uid from /proc, or use unix shell "id" where there is no /proc
     debug_fn!();
+    let status = fs::read_to_string("/proc/self/status").unwrap_or_default();
+    let uid = status
+        .lines()
+        .find_map(|line| line.strip_prefix("Uid:"))
+        .and_then(|ids| ids.split_whitespace().next());
+    if let Some(uid) = uid {
+        return Ok(uid.to_string());
+    }
     let command = format!("id");
     let output = execute_command(&command)?;
     let uid = output
@@ -91,8 +166,10 @@ pub fn execute_command_in_terminal(cmd: &str, args: Vec<&str>) -> Result<(), Hin
     cmd.stdout(Stdio::inherit());
     cmd.stderr(Stdio::inherit());
 
-    let mut child = cmd.spawn().expect("Failed to execute command");
-    let status = child.wait().expect("Failed to wait for command");
+    let status = match cmd.spawn().and_then(|mut child| child.wait()) {
+        Ok(status) => { status }
+        Err(err) => { return Err(HinaError::CommandExecError(format!("Unable to run {:?}: {}", cmd.get_program(), err))); }
+    };
 
     if status.success() {
         Ok(())
@@ -101,6 +178,54 @@ pub fn execute_command_in_terminal(cmd: &str, args: Vec<&str>) -> Result<(), Hin
     }
 }
 
+pub fn print_help(module_name: &str, usage: &str, flags: &Flag) -> Result<(), HinaError> {
+    // Help built from the flag declarations, --help=man opens the man page instead when man and the page are installed
+    debug_fn!(module_name,usage,flags);
+    if flags.parse_string(vec!["help"]) == "man" {
+        match execute_command_in_terminal("man", vec![&format!("hina-{}", module_name)]) {
+            Ok(_) => { return Ok(()); }
+            Err(_) => { eprintln!("Man page hina-{} is not available, showing the built-in help", module_name); }
+        }
+    }
+    println!("Usage: hina {} {}", module_name, usage);
+    if let Some(desc) = HELP_DICT.values().find_map(|operations| operations.get(module_name)) {
+        println!("{}", desc);
+    }
+    println!();
+    println!("Options:");
+    let specs = MODULE_MAP[module_name].get_flag_specs();
+    let synopses: Vec<String> = specs.iter().map(|spec| spec.get_synopsis()).collect();
+    let width = synopses.iter().map(|synopsis| synopsis.len()).max().unwrap_or(0);
+    for (synopsis, spec) in synopses.iter().zip(specs.iter()) {
+        if spec.default.is_empty() {
+            println!("  {:width$}  {}", synopsis, spec.help, width = width);
+        } else {
+            println!("  {:width$}  {} (default: {})", synopsis, spec.help, spec.default, width = width);
+        }
+    }
+    Ok(())
+}
+
+pub fn read_input(prompt: &str) -> Result<String, HinaError> {
+    // Print prompt and read a line from stdin
+    debug_fn!(prompt);
+    print!("{}", prompt);
+    let _ = stdout().flush();
+    let mut input = String::new();
+    match stdin().read_line(&mut input) {
+        Ok(0) => { Err(InputReadError(String::from("Unexpected end of input"))) }
+        Ok(_) => { Ok(input) }
+        Err(err) => { Err(InputReadError(err.to_string())) }
+    }
+}
+
+pub fn confirm(prompt: &str) -> Result<bool, HinaError> {
+    // Ask a yes/no question, anything but y or yes is a no
+    debug_fn!(prompt);
+    let answer = read_input(prompt)?.trim().to_lowercase();
+    Ok(answer == "y" || answer == "yes")
+}
+
 pub fn parse_path_or(input_path: Option<&String>, default: &str) -> Result<PathBuf, HinaError> {
     debug_fn!(input_path,default);
     match input_path {
@@ -113,6 +238,22 @@ pub fn parse_path_or(input_path: Option<&String>, default: &str) -> Result<PathB
     }
 }
 
+pub fn absolute_path(work_path: &PathBuf, input_path: &PathBuf) -> PathBuf {
+    // Make input_path absolute and resolve "." and ".." lexically, the path needs not exist
+    debug_fn!(work_path,input_path);
+    let mut joined = work_path.clone();
+    joined.push(input_path);
+    let mut result = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => { result.pop(); }
+            _ => { result.push(component); }
+        }
+    }
+    result
+}
+
 pub fn get_execute_target(work_path: &PathBuf, input_path: &PathBuf) -> Result<PathBuf, HinaError> {
     // Parse real execute target from input and return the abs path
     debug_fn!(work_path,input_path);
@@ -123,7 +264,8 @@ pub fn get_execute_target(work_path: &PathBuf, input_path: &PathBuf) -> Result<P
         target = work_path.clone();
         target.push(&input_path);
     }
-    if target.exists() {
+    // symlink_metadata so that dangling symlinks are found as well
+    if fs::symlink_metadata(&target).is_ok() {
         if !target.is_symlink() {
             match target.canonicalize() {
                 Ok(path) => { Ok(path) }
@@ -141,6 +283,273 @@ pub fn get_execute_target(work_path: &PathBuf, input_path: &PathBuf) -> Result<P
     }
 }
 
+pub fn move_path(src: &PathBuf, dst: &PathBuf) -> Result<(), HinaError> {
+    // Move src to dst, fall back to copy-then-delete when they are on different filesystems
+    debug_fn!(src,dst);
+    match fs::rename(src, dst) {
+        Ok(_) => { Ok(()) }
+        Err(err) if err.kind() == ErrorKind::CrossesDevices => {
+            debugln!("{} and {} are on different filesystems, copy then remove", src.display(), dst.display());
+            match copy_recursive(src, dst) {
+                Ok(_) => {}
+                Err(err) => {
+                    // Do not leave a half-copied target behind
+                    if fs::symlink_metadata(dst).is_ok() {
+                        let _ = remove_path(dst);
+                    }
+                    return Err(err);
+                }
+            }
+            match remove_path(src) {
+                Ok(_) => { Ok(()) }
+                Err(err) => {
+                    // Part of src may be gone already, so the copy is kept as the only full one
+                    let err = format!("Copied {} to {}, but {:?}", src.display(), dst.display(), err);
+                    Err(PartialMoveError(err))
+                }
+            }
+        }
+        Err(err) => {
+            let err = format!("Unable to move {} to {}: {}", src.display(), dst.display(), err);
+            Err(FileMoveError(err))
+        }
+    }
+}
+
+pub fn copy_recursive(src: &PathBuf, dst: &PathBuf) -> Result<(), HinaError> {
+    // Copy src to dst, keeping symlinks, permissions, ownership and timestamps
+    debug_fn!(src,dst);
+    let copy_err = |err: std::io::Error| {
+        FileCopyError(format!("Unable to copy {} to {}: {}", src.display(), dst.display(), err))
+    };
+    let meta = fs::symlink_metadata(src).map_err(copy_err)?;
+    let file_type = meta.file_type();
+    if file_type.is_symlink() {
+        let link = fs::read_link(src).map_err(copy_err)?;
+        symlink(&link, dst).map_err(copy_err)?;
+    } else if file_type.is_dir() {
+        fs::create_dir(dst).map_err(copy_err)?;
+        for entry in fs::read_dir(src).map_err(copy_err)? {
+            let entry = entry.map_err(copy_err)?;
+            let mut sub_dst = dst.clone();
+            sub_dst.push(entry.file_name());
+            copy_recursive(&entry.path(), &sub_dst)?;
+        }
+    } else if file_type.is_file() {
+        fs::copy(src, dst).map_err(copy_err)?;
+    } else {
+        let err = format!("Unable to copy {}: unsupported file type", src.display());
+        return Err(FileCopyError(err));
+    }
+    copy_metadata(&meta, dst)?;
+    Ok(())
+}
+
+fn copy_metadata(meta: &Metadata, dst: &PathBuf) -> Result<(), HinaError> {
+    debug_fn!(dst);
+    let copy_err = |err: std::io::Error| {
+        FileCopyError(format!("Unable to copy metadata to {}: {}", dst.display(), err))
+    };
+    // Changing owner only works for root, keep going as the current user otherwise
+    let _ = lchown(dst, Some(meta.uid()), Some(meta.gid()));
+    if meta.file_type().is_symlink() {
+        return Ok(());
+    }
+    let times = FileTimes::new()
+        .set_accessed(meta.accessed().map_err(copy_err)?)
+        .set_modified(meta.modified().map_err(copy_err)?);
+    let file = match File::open(dst) {
+        Ok(file) => { file }
+        Err(_) => { OpenOptions::new().write(true).open(dst).map_err(copy_err)? }
+    };
+    file.set_times(times).map_err(copy_err)?;
+    fs::set_permissions(dst, meta.permissions()).map_err(copy_err)?;
+    Ok(())
+}
+
+pub fn restore_meta(target: &PathBuf,
+                    mode: Option<u32>,
+                    uid: Option<u32>,
+                    gid: Option<u32>,
+                    mtime: Option<SystemTime>) -> Result<(), HinaError> {
+    // Put recorded metadata back, mode and modification time are left untouched for symlinks
+    debug_fn!(target,mode,uid,gid,mtime);
+    let restore_err = |err: std::io::Error| {
+        FileWriteError(format!("Unable to restore metadata of {}: {}", target.display(), err))
+    };
+    let meta = fs::symlink_metadata(target).map_err(restore_err)?;
+    if uid.is_some_and(|uid| uid != meta.uid()) || gid.is_some_and(|gid| gid != meta.gid()) {
+        // Changing owner only works for root, keep going as the current user otherwise
+        let _ = lchown(target, uid, gid);
+    }
+    if meta.file_type().is_symlink() {
+        return Ok(());
+    }
+    if let Some(mtime) = mtime {
+        let file = match File::open(target) {
+            Ok(file) => { file }
+            Err(_) => { OpenOptions::new().write(true).open(target).map_err(restore_err)? }
+        };
+        file.set_times(FileTimes::new().set_modified(mtime)).map_err(restore_err)?;
+    }
+    if let Some(mode) = mode {
+        fs::set_permissions(target, Permissions::from_mode(mode & 0o7777)).map_err(restore_err)?;
+    }
+    Ok(())
+}
+
+pub fn remove_path(target: &PathBuf) -> Result<(), HinaError> {
+    // Permanently remove a file, symlink or directory tree
+    debug_fn!(target);
+    let result = match fs::symlink_metadata(target) {
+        Ok(meta) if meta.is_dir() => { fs::remove_dir_all(target) }
+        Ok(_) => { fs::remove_file(target) }
+        Err(err) => { Err(err) }
+    };
+    match result {
+        Ok(_) => { Ok(()) }
+        Err(err) => {
+            let err = format!("Unable to remove {}: {}", target.display(), err);
+            Err(FileRemoveError(err))
+        }
+    }
+}
+
+pub fn shred_file(target: &PathBuf, passes: usize, zero: bool) -> Result<(), HinaError> {
+    // Overwrite the content of a regular file in place, with random bytes or zeros
+    debug_fn!(target,passes,zero);
+    // Read only files like chmod 400 keys are made writable first, they are unlinked right after
+    match fs::metadata(target) {
+        Ok(meta) if meta.permissions().mode() & 0o200 == 0 => {
+            let permissions = Permissions::from_mode(meta.permissions().mode() | 0o200);
+            match fs::set_permissions(target, permissions) {
+                Ok(_) => {}
+                Err(err) => { return Err(FileWriteError(format!("Unable to make {} writable: {}", target.display(), err))); }
+            }
+        }
+        _ => {}
+    }
+    let mut file = match OpenOptions::new().write(true).open(target) {
+        Ok(file) => { file }
+        Err(err) => { return Err(FileOpenError(format!("Unable to open {}: {}", target.display(), err))); }
+    };
+    let len = match file.metadata() {
+        Ok(meta) => { meta.len() }
+        Err(err) => { return Err(FileOpenError(format!("Unable to open {}: {}", target.display(), err))); }
+    };
+    let mut buffer = vec![0u8; SHRED_BLOCK_SIZE];
+    let mut rng = thread_rng();
+    for _ in 0..passes {
+        let mut written = 0;
+        let result = file.seek(SeekFrom::Start(0)).and_then(|_| {
+            while written < len {
+                let size = min(len - written, SHRED_BLOCK_SIZE as u64) as usize;
+                if !zero {
+                    rng.fill(&mut buffer[..size]);
+                }
+                file.write_all(&buffer[..size])?;
+                written += size as u64;
+            }
+            // Make sure each pass reaches the disk instead of being merged in the page cache
+            file.sync_data()
+        });
+        match result {
+            Ok(_) => {}
+            Err(err) => { return Err(FileWriteError(format!("Unable to overwrite {}: {}", target.display(), err))); }
+        }
+    }
+    Ok(())
+}
+
+pub fn shred_path(target: &PathBuf,
+                  passes: usize,
+                  zero: bool,
+                  linked: &mut Vec<PathBuf>,
+                  failures: &mut Vec<HinaError>) -> Result<(), HinaError> {
+    // Shred every regular file under target then remove it, files with other hard links are
+    // only unlinked and collected in linked, overwriting them would destroy the other copies.
+    // An entry inside that fails is collected in failures and the others are still shredded.
+    debug_fn!(target,passes,zero);
+    let meta = match fs::symlink_metadata(target) {
+        Ok(meta) => { meta }
+        Err(err) => { return Err(FileRemoveError(format!("Unable to remove {}: {}", target.display(), err))); }
+    };
+    if meta.is_dir() {
+        let entries = match fs::read_dir(target) {
+            Ok(entries) => { entries }
+            Err(err) => { return Err(DirReadError(format!("Unable to read {}: {}", target.display(), err))); }
+        };
+        let before = failures.len();
+        for entry in entries.flatten() {
+            match shred_path(&entry.path(), passes, zero, linked, failures) {
+                Ok(_) => {}
+                Err(err) => { failures.push(err); }
+            }
+        }
+        if failures.len() > before {
+            let err = format!("Unable to remove {}, {} failures inside", target.display(), failures.len() - before);
+            return Err(FileRemoveError(err));
+        }
+    } else if meta.is_file() {
+        if meta.nlink() > 1 {
+            linked.push(target.clone());
+        } else {
+            shred_file(target, passes, zero)?;
+        }
+    }
+    remove_path(target)
+}
+
+fn unescape_mount_field(field: &str) -> String {
+    // Fields in mountinfo escape space, tab, newline and backslash as \ooo
+    debug_fn!(field);
+    let bytes = field.as_bytes();
+    let mut result = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && i + 3 < bytes.len() {
+            let code = String::from_utf8_lossy(&bytes[i + 1..i + 4]).to_string();
+            if let Ok(byte) = u8::from_str_radix(&code, 8) {
+                result.push(byte);
+                i += 4;
+                continue;
+            }
+        }
+        result.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&result).to_string()
+}
+
+pub fn get_mount_points() -> Result<Vec<PathBuf>, HinaError> {
+    // Read all mount points of the current mount namespace
+    debug_fn!();
+    let content = match fs::read_to_string(MOUNT_INFO) {
+        Ok(content) => { content }
+        Err(err) => { return Err(FileOpenError(format!("Unable to read {}: {}", MOUNT_INFO, err))); }
+    };
+    let mut mount_points = Vec::new();
+    for line in content.lines() {
+        let fields: Vec<&str> = line.split(' ').collect();
+        if fields.len() > 4 {
+            mount_points.push(PathBuf::from(unescape_mount_field(fields[4])));
+        }
+    }
+    Ok(mount_points)
+}
+
+pub fn get_mount_point(target: &Path) -> Result<PathBuf, HinaError> {
+    // Find the mount point holding target, i.e. the longest mount point prefixing it
+    debug_fn!(target);
+    let mut mount_point = PathBuf::from("/");
+    for point in get_mount_points()? {
+        if target.starts_with(&point) && point.components().count() > mount_point.components().count() {
+            mount_point = point;
+        }
+    }
+    Ok(mount_point)
+}
+
 pub fn init_data_dir(data_path: &PathBuf) -> Result<(), HinaError> {
     // Hina utils, for initializing the Hina data dir containing recycle bin and etc.
     debug_fn!(data_path);
@@ -174,44 +583,296 @@ pub fn init_data_dir(data_path: &PathBuf) -> Result<(), HinaError> {
     Ok(())
 }
 
+pub fn lock_data_dir(data_path: &PathBuf) -> Result<File, HinaError> {
+    // Take an exclusive advisory lock on the Hina data dir, released when the returned file is dropped
+    debug_fn!(data_path);
+    let mut lock_path = data_path.clone();
+    lock_path.push(LOCK_FILE);
+    let lock_file = match OpenOptions::new().write(true).create(true).truncate(false).open(&lock_path) {
+        Ok(file) => { file }
+        Err(err) => { return Err(FileLockError(format!("Unable to open {}: {}", lock_path.display(), err))); }
+    };
+    match lock_file.try_lock() {
+        Ok(_) => { return Ok(lock_file); }
+        Err(TryLockError::WouldBlock) => {
+            eprintln!("Waiting for another hina process to release {}", lock_path.display());
+        }
+        Err(TryLockError::Error(err)) => {
+            return Err(FileLockError(format!("Unable to lock {}: {}", lock_path.display(), err)));
+        }
+    }
+    match lock_file.lock() {
+        Ok(_) => { Ok(lock_file) }
+        Err(err) => { Err(FileLockError(format!("Unable to lock {}: {}", lock_path.display(), err))) }
+    }
+}
+
 pub fn load_rm_stack(data_path: &PathBuf) -> Result<Vec<RMRecord>, HinaError> {
     // Load the RM_STACK for recycle bin
     debug_fn!(data_path);
     let mut rm_stack_path = data_path.clone();
     rm_stack_path.push(RM_STACK);
-    let file = match File::open(rm_stack_path) {
-        Ok(file) => { file }
+    let content = match fs::read_to_string(&rm_stack_path) {
+        Ok(content) => { content }
+        Err(err) if err.kind() == ErrorKind::NotFound => { return Ok(Vec::new()); }
         Err(err) => { return Err(FileOpenError(err.to_string())); }
     };
-    let reader = BufReader::new(file);
-    let rm_stack = match serde_json::from_reader(reader) {
-        Ok(rm_stack) => { rm_stack }
-        Err(_) => { Vec::new() }
-    };
-    Ok(rm_stack)
+    // A fresh RM_STACK is an empty file
+    if content.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    match serde_json::from_str(&content) {
+        Ok(rm_stack) => { Ok(rm_stack) }
+        Err(err) => {
+            // Keep a copy and stop, saving an empty stack over it would lose the whole history
+            let now: DateTime<Local> = Local::now();
+            let backup_path = PathBuf::from(format!("{}.corrupt-{}", rm_stack_path.display(), now.format("%Y%m%d%H%M%S")));
+            let backup = match fs::copy(&rm_stack_path, &backup_path) {
+                Ok(_) => { format!("a copy is saved to {}", backup_path.display()) }
+                Err(err) => { format!("unable to back it up to {}: {}", backup_path.display(), err) }
+            };
+            let err = format!("{} is corrupt ({}), {}. Fix or remove it to continue", rm_stack_path.display(), err, backup);
+            Err(StackCorruptError(err))
+        }
+    }
 }
 
 pub fn save_rm_stack(data_path: &PathBuf,
                      rm_stack: &Vec<RMRecord>) -> Result<(), HinaError> {
-    // Write RM_STACK back to disk
+    // Write RM_STACK to a temp file and rename it over the old one, so a crash never leaves it half written
     debug_fn!(rm_stack);
     let mut rm_stack_path = data_path.clone();
     rm_stack_path.push(RM_STACK);
+    let tmp_path = PathBuf::from(format!("{}.tmp.{}", rm_stack_path.display(), std::process::id()));
     let file = match OpenOptions::new()
-        .read(true)
         .write(true)
         .create(true)
         .truncate(true)
-        .open(rm_stack_path) {
+        .open(&tmp_path) {
         Ok(file) => { file }
         Err(err) => { return Err(FileOpenError(err.to_string())); }
     };
-    let writer = BufWriter::new(file);
-    match serde_json::to_writer(writer, rm_stack) {
-        Ok(_) => {}
-        Err(err) => { return Err(FileWriteError(err.to_string())); }
+    let mut writer = BufWriter::new(file);
+    let written = match serde_json::to_writer(&mut writer, rm_stack) {
+        Ok(_) => {
+            match writer.into_inner() {
+                Ok(file) => { file.sync_all().map_err(|err| err.to_string()) }
+                Err(err) => { Err(err.to_string()) }
+            }
+        }
+        Err(err) => { Err(err.to_string()) }
+    };
+    match written.and_then(|_| fs::rename(&tmp_path, &rm_stack_path).map_err(|err| err.to_string())) {
+        Ok(_) => { Ok(()) }
+        Err(err) => {
+            let _ = fs::remove_file(&tmp_path);
+            Err(FileWriteError(err))
+        }
+    }
+}
+
+pub fn parse_index_range(input: &str) -> Option<(usize, usize)> {
+    // Parse "3" or "3-7" into an inclusive range
+    debug_fn!(input);
+    let input = input.trim();
+    match input.split_once('-') {
+        Some((start, end)) => {
+            let start: usize = start.trim().parse().ok()?;
+            let end: usize = end.trim().parse().ok()?;
+            if start <= end { Some((start, end)) } else { None }
+        }
+        None => {
+            let index: usize = input.parse().ok()?;
+            Some((index, index))
+        }
+    }
+}
+
+pub fn is_glob(input: &str) -> bool {
+    debug_fn!(input);
+    input.contains(['*', '?', '['])
+}
+
+pub fn build_glob(work_path: &PathBuf, pattern: &str) -> Result<Pattern, HinaError> {
+    // Relative patterns are matched against paths under work_path
+    debug_fn!(work_path,pattern);
+    let full_pattern = absolute_path(work_path, &PathBuf::from(pattern));
+    match Pattern::new(&full_pattern.display().to_string()) {
+        Ok(pattern) => { Ok(pattern) }
+        Err(err) => { Err(PatternParseError(format!("Invalid pattern \'{}\': {}", pattern, err))) }
     }
-    Ok(())
+}
+
+pub fn glob_match(pattern: &Pattern, path: &str) -> bool {
+    // Like the shell, '*' and '?' do not match across '/'
+    debug_fn!(pattern,path);
+    let options = MatchOptions {
+        case_sensitive: true,
+        require_literal_separator: true,
+        require_literal_leading_dot: false,
+    };
+    pattern.matches_with(path, options)
+}
+
+pub fn parse_size(input: &str) -> Result<u64, HinaError> {
+    // Parse sizes like 512, 100K, 1.5G into bytes, units are powers of 1024
+    debug_fn!(input);
+    let input = input.trim();
+    let (number, unit) = match input.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(pos) => { (&input[..pos], input[pos..].to_ascii_uppercase()) }
+        None => { (input, String::new()) }
+    };
+    let scale: u64 = match unit.trim_end_matches(['B', 'I']) {
+        "" => { 1 }
+        "K" => { 1 << 10 }
+        "M" => { 1 << 20 }
+        "G" => { 1 << 30 }
+        "T" => { 1 << 40 }
+        _ => { return Err(ConfigParseError(format!("Invalid size \'{}\'", input))); }
+    };
+    match number.trim().parse::<f64>() {
+        Ok(number) if number >= 0.0 => { Ok((number * scale as f64) as u64) }
+        _ => { Err(ConfigParseError(format!("Invalid size \'{}\'", input))) }
+    }
+}
+
+pub fn parse_duration(input: &str) -> Result<Duration, HinaError> {
+    // Parse durations like 90s, 45m, 12h, 30d, 2w
+    debug_fn!(input);
+    let input = input.trim();
+    let err = || ConfigParseError(format!("Invalid duration \'{}\'", input));
+    // The unit is the last char, which may take more than one byte
+    let (unit_pos, unit) = match input.char_indices().last() {
+        Some(last) => { last }
+        None => { return Err(err()); }
+    };
+    let number: i64 = match input[..unit_pos].parse() {
+        Ok(number) if number >= 0 => { number }
+        _ => { return Err(err()); }
+    };
+    let duration = match unit {
+        's' => { Duration::try_seconds(number) }
+        'm' => { Duration::try_minutes(number) }
+        'h' => { Duration::try_hours(number) }
+        'd' => { Duration::try_days(number) }
+        'w' => { Duration::try_weeks(number) }
+        _ => { return Err(err()); }
+    };
+    match duration {
+        Some(duration) => { Ok(duration) }
+        None => { Err(ConfigParseError(format!("Duration \'{}\' is too long", input))) }
+    }
+}
+
+pub fn time_ago(age: Duration) -> Result<NaiveDateTime, HinaError> {
+    // The local time age ago, an age reaching before the earliest supported date is an error
+    debug_fn!(age);
+    match Local::now().naive_local().checked_sub_signed(age) {
+        Some(time) => { Ok(time) }
+        None => { Err(ConfigParseError(format!("Age of {} days is too long", age.num_days()))) }
+    }
+}
+
+pub fn parse_time(input: &str) -> Option<NaiveDateTime> {
+    // Parse times written by Hina, e.g. the delete time of a record
+    debug_fn!(input);
+    NaiveDateTime::parse_from_str(input, TIME_FORMAT).ok()
+}
+
+pub fn parse_time_point(input: &str) -> Result<NaiveDateTime, HinaError> {
+    // Parse an absolute time like 2023-11-20 or 2023-11-20 08:00:00, or an age like 3d meaning 3 days ago
+    debug_fn!(input);
+    let input = input.trim();
+    if let Ok(duration) = parse_duration(input) {
+        return time_ago(duration);
+    }
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S"] {
+        if let Ok(time) = NaiveDateTime::parse_from_str(input, format) {
+            return Ok(time);
+        }
+    }
+    match NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+        Ok(date) => { Ok(date.and_time(NaiveTime::MIN)) }
+        Err(_) => { Err(ConfigParseError(format!("Invalid time \'{}\'", input))) }
+    }
+}
+
+pub fn format_age(age: Duration) -> String {
+    // Show the two largest units, e.g. 3d4h, 5m12s
+    debug_fn!(age);
+    let seconds = max(age.num_seconds(), 0);
+    let units = [("d", 86400), ("h", 3600), ("m", 60), ("s", 1)];
+    for (i, (unit, length)) in units.iter().enumerate() {
+        if seconds >= *length || *unit == "s" {
+            let mut result = format!("{}{}", seconds / length, unit);
+            if i + 1 < units.len() {
+                let (next_unit, next_length) = units[i + 1];
+                let rest = seconds % length / next_length;
+                if rest > 0 {
+                    result += &format!("{}{}", rest, next_unit);
+                }
+            }
+            return result;
+        }
+    }
+    String::new()
+}
+
+pub fn display_path(work_path: &PathBuf, path: &str) -> String {
+    // Show paths under work_path relative to it
+    debug_fn!(work_path,path);
+    match Path::new(path).strip_prefix(work_path) {
+        Ok(relative) if relative.as_os_str().is_empty() => { String::from(".") }
+        Ok(relative) => { relative.display().to_string() }
+        Err(_) => { path.to_string() }
+    }
+}
+
+pub fn format_size(size: u64) -> String {
+    debug_fn!(size);
+    let mut num = size as f64;
+    for unit in ["B", "KB", "MB", "GB"] {
+        if num < 1024f64 {
+            return if unit == "B" { format!("{} B", size) } else { format!("{:.3} {}", num, unit) };
+        }
+        num /= 1024.0;
+    }
+    format!("{:.3} TB", num)
+}
+
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    // Levenshtein distance, used to suggest the intended name of a mistyped one
+    debug_fn!(a,b);
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            cur.push(min(min(prev[j + 1] + 1, cur[j] + 1), prev[j] + cost));
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+pub fn get_size(target: &PathBuf) -> u64 {
+    // Size in bytes, recursive for directories and without following symlinks
+    debug_fn!(target);
+    let meta = match fs::symlink_metadata(target) {
+        Ok(meta) => { meta }
+        Err(_) => { return 0; }
+    };
+    if !meta.is_dir() {
+        return meta.len();
+    }
+    let mut size = meta.len();
+    if let Ok(entries) = fs::read_dir(target) {
+        for entry in entries.flatten() {
+            size += get_size(&entry.path());
+        }
+    }
+    size
 }
 
 pub fn split_and_remove_blank(content: &String, pattern: &str) -> Result<Vec<String>, HinaError> {
@@ -248,20 +909,21 @@ pub fn print_info(head: &Vec<String>,
                   data: &Vec<Vec<String>>,
                   n_len: usize) {
     debug_fn!();
-    let mut max_len: Vec<usize> = vec![0; n_len];
+    let mut max_len: Vec<usize> = head.iter().take(n_len).map(|x| x.len()).collect();
     for line in data {
         for i in 0..n_len {
             max_len[i] = max(max_len[i], line[i].len());
         }
     }
-    for i in 0..n_len {
+    // The last column is not padded, so a long command does not widen every line
+    for i in 0..n_len - 1 {
         print!("{}  ", gen_str_width_ctrl(&head[i], max_len[i]));
     }
-    println!();
+    println!("{}", head[n_len - 1]);
     for d in data {
-        for i in 0..n_len {
+        for i in 0..n_len - 1 {
             print!("{}  ", gen_str_width_ctrl(&d[i], max_len[i]));
         }
-        println!();
+        println!("{}", d[n_len - 1]);
     }
 }
\ No newline at end of file
@@ -1,11 +1,16 @@
 use std::collections::HashMap;
 use std::env;
 
+use chrono::Duration;
 use indexmap::IndexMap;
 use once_cell::sync::Lazy;
 use regex::Regex;
 
-use crate::core::config::Module;
+use crate::core::config::{BinStore, Compression, Module, TrashBackend};
+use crate::core::error::HinaError;
+use crate::core::func;
+use crate::core::settings::Settings;
+use crate::event::completion::Completion;
 use crate::event::fs::{LinkConvert, MakeNestedDir, Rename};
 use crate::event::process::Process;
 use crate::event::recycle::{RecycleBin, Remove};
@@ -17,6 +22,39 @@ pub static DEBUG: Lazy<bool> = Lazy::new(|| {
     }
 });
 
+pub static SETTINGS: Lazy<Result<Settings, String>> = Lazy::new(|| {
+    Settings::load().map_err(|err| match err {
+        HinaError::ConfigParseError(err) | HinaError::FileOpenError(err) => { err }
+        err => { format!("{:?}", err) }
+    })
+});
+
+pub static TRASH_BACKEND: Lazy<TrashBackend> = Lazy::new(|| {
+    TrashBackend::from(&func::get_trash_backend_name()).unwrap_or(TrashBackend::Hina)
+});
+
+pub static BIN_QUOTA: Lazy<Option<u64>> = Lazy::new(|| {
+    let quota = func::get_bin_quota_str();
+    if quota.is_empty() { None } else { func::parse_size(&quota).ok() }
+});
+
+pub static BIN_STORE: Lazy<BinStore> = Lazy::new(|| {
+    BinStore::from(&func::get_bin_store_name()).unwrap_or(BinStore::Plain)
+});
+
+pub static BIN_COMPRESS: Lazy<Compression> = Lazy::new(|| {
+    Compression::from(&func::get_bin_compress_name()).unwrap_or(Compression::None)
+});
+
+pub static BIN_COMPRESS_AFTER: Lazy<Duration> = Lazy::new(|| {
+    func::parse_duration(&func::get_bin_compress_after_str()).unwrap_or(Duration::days(DEFAULT_COMPRESS_DAYS))
+});
+
+pub static BATCH_ID: Lazy<String> = Lazy::new(|| {
+    // Shared by every record of one hina invocation
+    func::gen_rand_str(BATCH_ID_LEN)
+});
+
 pub static MODULE_MAP: Lazy<HashMap<&str, Module>> = Lazy::new(|| {
     HashMap::from([
         ("rm", Module::Remove(Remove)),
@@ -25,6 +63,7 @@ pub static MODULE_MAP: Lazy<HashMap<&str, Module>> = Lazy::new(|| {
         ("rn", Module::Rename(Rename)),
         ("lc", Module::LinkConvert(LinkConvert)),
         ("ps", Module::Process(Process)),
+        ("completions", Module::Completion(Completion)),
     ])
 });
 
@@ -42,14 +81,58 @@ pub static HELP_DICT: Lazy<IndexMap<&str, IndexMap<&str, &str>>> = Lazy::new(||
         ("Powerful process utils", IndexMap::from([
             ("ps", "Advanced process checker, can see swap/pss/rss utilization and track process ancestor."),
         ])),
+        ("Shell integration", IndexMap::from([
+            ("completions", "Print the completion script for bash, zsh or fish."),
+        ])),
     ])
 });
 
 pub static DATA_DIR: &str = ".hina";
+pub static CONFIG_FILE: &str = "config.toml";
+pub static SYSTEM_CONFIG: &str = "/etc/hina/config.toml";
 pub static RM_STACK: &str = "rm.stack";
+pub static LOCK_FILE: &str = "hina.lock";
+pub static PROTECT_FILE: &str = "protect";
+pub static DEFAULT_PROTECT_RULES: [&str; 8] = [
+    "deny /",
+    "deny /etc",
+    "deny $HOME",
+    "deny $HOME/.hina",
+    "deny $HOME/.hina/**",
+    "deny .hina-trash",
+    "deny **/.hina-trash/**",
+    "confirm .git",
+];
 pub static RECYCLE: &str = "RecycleBin";
+pub static MOUNT_TRASH: &str = ".hina-trash";
+pub static QUARANTINE: &str = "Quarantine";
+pub static STORE: &str = "store";
+pub static ZSTD_EXT: &str = "zst";
+pub static GZIP_EXT: &str = "gz";
+pub static DEFAULT_COMPRESS_DAYS: i64 = 7;
+pub static DEFAULT_SHRED_PASSES: usize = 3;
+pub static DEFAULT_WATCH_INTERVAL: &str = "2s";
+// Changed mappings listed per process by ps --diff
+pub static DIFF_MAX_MAPPINGS: usize = 10;
+// ps --analyze calls it a leak when PSS+Swap grew by this share over enough samples,
+// and only a likely one when the growth also fits a straight line this well
+pub static LEAK_MIN_GROWTH: f64 = 0.05;
+pub static LEAK_MIN_R2: f64 = 0.8;
+pub static LEAK_MIN_SAMPLES: usize = 3;
+pub static SHRED_BLOCK_SIZE: usize = 1 << 16;
+pub static UNKNOWN_ORIGIN: &str = "unknown origin";
+pub static MOUNT_INFO: &str = "/proc/self/mountinfo";
+pub static XDG_TRASH: &str = "Trash";
+pub static XDG_TRASH_INFO_EXT: &str = "trashinfo";
+pub static XDG_BATCH_KEY: &str = "X-Hina-Batch";
 pub static RAND_STR_LEN: usize = 16;
+pub static BATCH_ID_LEN: usize = 8;
+pub static TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.3f";
+// TIME_FORMAT without the milliseconds, for display
+pub static SECOND_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
 pub static MAX_RECURSIVE_DEPTH: usize = 64;
+// USER_HZ, the unit of the times in /proc/<pid>/stat, is 100 on every Linux ABI
+pub static CLOCK_TICKS: u64 = 100;
 pub static MEM_EXTRACT_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?P<name>\S+):\s+(?P<amount>\d+) kB").unwrap());
 
 #[macro_export]
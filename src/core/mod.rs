@@ -2,4 +2,9 @@ pub mod config;
 pub mod executor;
 pub mod global;
 pub mod func;
-pub mod error;
\ No newline at end of file
+pub mod error;
+pub mod xdg;
+pub mod protect;
+pub mod store;
+pub mod settings;
+pub mod term;
\ No newline at end of file
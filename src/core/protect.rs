@@ -0,0 +1,174 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use colored::Colorize;
+use glob::Pattern;
+
+use crate::{debug_fn, debug_info, debugln};
+use crate::core::error::HinaError;
+use crate::core::error::HinaError::{ConfigParseError, FileOpenError, PatternParseError};
+use crate::core::func;
+use crate::core::global::{DEBUG, DEFAULT_PROTECT_RULES, PROTECT_FILE};
+
+// Protected path rules checked by rm before moving anything, the defaults plus those read
+// from $HOME/.hina/protect, one "deny PATTERN" or "confirm PATTERN" per line.
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ProtectAction {
+    Deny,
+    Confirm,
+}
+
+#[derive(Debug)]
+pub struct ProtectRule {
+    action: ProtectAction,
+    rule: String,
+    pattern: Pattern,
+    // Patterns without '/' are matched against the file name only, like .git
+    name_only: bool,
+    // A literal path is also protected from removing any of its ancestors
+    literal: Option<PathBuf>,
+    // Everything under the literal path as well, used for the bins and the data dir
+    subtree: bool,
+}
+
+impl ProtectRule {
+    pub fn from(line: &str, home: &PathBuf) -> Result<ProtectRule, HinaError> {
+        debug_fn!(line,home);
+        let (action, rule) = match line.trim().split_once(char::is_whitespace) {
+            Some(("deny", rule)) => { (ProtectAction::Deny, rule.trim()) }
+            Some(("confirm", rule)) => { (ProtectAction::Confirm, rule.trim()) }
+            _ => { return Err(ConfigParseError(format!("Expect \'deny PATTERN\' or \'confirm PATTERN\', got \'{}\'", line))); }
+        };
+        let name_only = !rule.contains('/');
+        let expanded = if let Some(rest) = rule.strip_prefix("$HOME") {
+            format!("{}{}", home.display(), rest)
+        } else if let Some(rest) = rule.strip_prefix('~') {
+            format!("{}{}", home.display(), rest)
+        } else {
+            rule.to_string()
+        };
+        if !name_only && !expanded.starts_with('/') && !expanded.starts_with("**/") {
+            return Err(ConfigParseError(format!("Protected path \'{}\' must be absolute or start with ~, $HOME or **/", rule)));
+        }
+        let literal = if func::is_glob(&expanded) || name_only {
+            None
+        } else {
+            Some(resolve_path(&PathBuf::from(&expanded)))
+        };
+        let pattern_str = match &literal {
+            Some(path) => { Pattern::escape(&path.display().to_string()) }
+            None => { expanded }
+        };
+        let pattern = match Pattern::new(&pattern_str) {
+            Ok(pattern) => { pattern }
+            Err(err) => { return Err(PatternParseError(format!("Invalid pattern \'{}\': {}", rule, err))); }
+        };
+        Ok(ProtectRule { action, rule: rule.to_string(), pattern, name_only, literal, subtree: false })
+    }
+
+    pub fn guard(path: &Path) -> Result<ProtectRule, HinaError> {
+        // Deny path, its ancestors and everything under it, whatever the rules say, so the
+        // bins are never moved into themselves
+        debug_fn!(path);
+        let literal = resolve_path(&path.to_path_buf());
+        let pattern = match Pattern::new(&Pattern::escape(&literal.display().to_string())) {
+            Ok(pattern) => { pattern }
+            Err(err) => { return Err(PatternParseError(format!("Invalid path \'{}\': {}", path.display(), err))); }
+        };
+        let rule = format!("{}/**", path.display());
+        Ok(ProtectRule { action: ProtectAction::Deny, rule, pattern, name_only: false, literal: Some(literal), subtree: true })
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        debug_fn!(path);
+        if let Some(literal) = &self.literal {
+            return literal.starts_with(path) || (self.subtree && path.starts_with(literal));
+        }
+        if self.name_only {
+            return path
+                .file_name()
+                .is_some_and(|name| func::glob_match(&self.pattern, &name.to_string_lossy()));
+        }
+        func::glob_match(&self.pattern, &path.display().to_string())
+    }
+
+    pub fn get_action(&self) -> ProtectAction {
+        debug_fn!();
+        self.action
+    }
+
+    pub fn get_rule(&self) -> &String {
+        debug_fn!();
+        &self.rule
+    }
+}
+
+pub fn resolve_path(path: &PathBuf) -> PathBuf {
+    // Resolve symlinks and '..' of the parents, the last component is kept as is so a
+    // symlink is checked as itself rather than as what it points to
+    debug_fn!(path);
+    let (parent, name) = match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => { (parent.to_path_buf(), name) }
+        // Ends with '..' or is '/'
+        _ => { return path.canonicalize().unwrap_or(func::absolute_path(&PathBuf::from("/"), path)); }
+    };
+    let mut resolved = parent.canonicalize().unwrap_or(func::absolute_path(&PathBuf::from("/"), &parent));
+    resolved.push(name);
+    match fs::symlink_metadata(&resolved) {
+        Ok(meta) if !meta.is_symlink() => { resolved.canonicalize().unwrap_or(resolved) }
+        _ => { resolved }
+    }
+}
+
+pub fn load_rules(data_path: &PathBuf, guarded: &[PathBuf]) -> Result<Vec<ProtectRule>, HinaError> {
+    // The default rules, those of the protect file when there is one, and a guard of each of
+    // guarded, the protect file adds rules and never drops the defaults
+    debug_fn!(data_path,guarded);
+    let home = PathBuf::from(func::get_home()?);
+    let home = home.canonicalize().unwrap_or(home);
+    let mut rules = Vec::new();
+    parse_rules("default protect rules", &DEFAULT_PROTECT_RULES.join("\n"), &home, &mut rules)?;
+    let mut protect_path = data_path.clone();
+    protect_path.push(PROTECT_FILE);
+    match fs::read_to_string(&protect_path) {
+        Ok(content) => { parse_rules(&protect_path.display().to_string(), &content, &home, &mut rules)?; }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            debugln!("{} not found, only the default rules apply", protect_path.display());
+        }
+        Err(err) => { return Err(FileOpenError(format!("Unable to open {}: {}", protect_path.display(), err))); }
+    }
+    for path in guarded {
+        rules.push(ProtectRule::guard(path)?);
+    }
+    Ok(rules)
+}
+
+fn parse_rules(source: &str, content: &str, home: &PathBuf, rules: &mut Vec<ProtectRule>) -> Result<(), HinaError> {
+    debug_fn!(source);
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match ProtectRule::from(line, home) {
+            Ok(rule) => { rules.push(rule); }
+            Err(ConfigParseError(err)) | Err(PatternParseError(err)) => {
+                return Err(ConfigParseError(format!("{} line {}: {}", source, i + 1, err)));
+            }
+            Err(err) => { return Err(err); }
+        }
+    }
+    Ok(())
+}
+
+pub fn check<'a>(rules: &'a [ProtectRule], path: &Path) -> Option<&'a ProtectRule> {
+    // The first deny rule matching path, or else the first confirm rule
+    debug_fn!(path);
+    let mut matched = rules.iter().filter(|rule| rule.matches(path));
+    let first = matched.next()?;
+    if first.get_action() == ProtectAction::Deny {
+        return Some(first);
+    }
+    Some(matched.find(|rule| rule.get_action() == ProtectAction::Deny).unwrap_or(first))
+}
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use colored::Colorize;
+use serde::Deserialize;
+use toml::Table;
+
+use crate::{debug_fn, debug_info, debugln};
+use crate::core::error::HinaError;
+use crate::core::error::HinaError::{ConfigParseError, FileOpenError};
+use crate::core::func;
+use crate::core::global::{CONFIG_FILE, DATA_DIR, DEBUG, MODULE_MAP, SYSTEM_CONFIG};
+
+// Settings from /etc/hina/config.toml overlaid by the user config file, which is
+// $HINA_CONFIG or $HOME/.hina/config.toml. Environment variables win over both.
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct BinSettings {
+    pub path: String,
+    pub backend: String,
+    pub quota: String,
+    pub store: String,
+    pub compress: String,
+    pub compress_after: String,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputSettings {
+    pub format: String,
+    pub color: String,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub bin: BinSettings,
+    pub output: OutputSettings,
+    // Default flags per module, like rm = ["-v", "--preserve-root=all"]
+    pub flags: HashMap<String, Vec<String>>,
+}
+
+impl Settings {
+    pub fn load() -> Result<Settings, HinaError> {
+        debug_fn!();
+        let mut merged = Table::new();
+        if let Some(table) = read_table(&PathBuf::from(SYSTEM_CONFIG), false)? {
+            merge_table(&mut merged, table);
+        }
+        let (user_config, required) = match func::get_config_path_str() {
+            path if !path.is_empty() => { (PathBuf::from(path), true) }
+            _ => {
+                let mut path = PathBuf::from(func::get_home()?);
+                path.push(DATA_DIR);
+                path.push(CONFIG_FILE);
+                (path, false)
+            }
+        };
+        if let Some(table) = read_table(&user_config, required)? {
+            merge_table(&mut merged, table);
+        }
+        Settings::from(merged)
+    }
+
+    pub fn from(table: Table) -> Result<Settings, HinaError> {
+        debug_fn!(table);
+        let settings: Settings = match table.try_into() {
+            Ok(settings) => { settings }
+            Err(err) => { return Err(ConfigParseError(format!("Invalid config: {}", err.to_string().trim()))); }
+        };
+        for (module, flags) in settings.flags.iter() {
+            if !MODULE_MAP.contains_key(module.as_str()) {
+                return Err(ConfigParseError(format!("Unknown module \'{}\' in [flags]", module)));
+            }
+            if let Some(flag) = flags.iter().find(|flag| !flag.starts_with('-')) {
+                return Err(ConfigParseError(format!("Default flag \'{}\' of {} must start with \'-\'", flag, module)));
+            }
+        }
+        match settings.output.format.as_str() {
+            "" | "table" | "json" => {}
+            format => { return Err(ConfigParseError(format!("Unknown output format \'{}\', expect table or json", format))); }
+        }
+        match settings.output.color.as_str() {
+            "" | "auto" | "always" | "never" => {}
+            color => { return Err(ConfigParseError(format!("Unknown color setting \'{}\', expect auto, always or never", color))); }
+        }
+        Ok(settings)
+    }
+
+    pub fn get_bin_path(&self) -> Result<Option<PathBuf>, HinaError> {
+        // The recycle bin location with ~ and $HOME expanded
+        debug_fn!();
+        let path = &self.bin.path;
+        if path.is_empty() {
+            return Ok(None);
+        }
+        let home = func::get_home()?;
+        let expanded = if let Some(rest) = path.strip_prefix("$HOME") {
+            format!("{}{}", home, rest)
+        } else if let Some(rest) = path.strip_prefix('~') {
+            format!("{}{}", home, rest)
+        } else {
+            path.clone()
+        };
+        if !expanded.starts_with('/') {
+            return Err(ConfigParseError(format!("Bin path \'{}\' must be absolute or start with ~ or $HOME", path)));
+        }
+        Ok(Some(PathBuf::from(expanded)))
+    }
+
+    pub fn get_flags(&self, module: &str) -> Vec<String> {
+        debug_fn!(module);
+        self.flags.get(module).cloned().unwrap_or_default()
+    }
+}
+
+fn read_table(path: &PathBuf, required: bool) -> Result<Option<Table>, HinaError> {
+    debug_fn!(path,required);
+    let content = match fs::read_to_string(path) {
+        Ok(content) => { content }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound && !required => {
+            debugln!("{} not found, skip", path.display());
+            return Ok(None);
+        }
+        Err(err) => { return Err(FileOpenError(format!("Unable to open {}: {}", path.display(), err))); }
+    };
+    match content.parse::<Table>() {
+        Ok(table) => { Ok(Some(table)) }
+        Err(err) => { Err(ConfigParseError(format!("{}: {}", path.display(), err.to_string().trim()))) }
+    }
+}
+
+pub fn merge_table(base: &mut Table, over: Table) {
+    // Tables are merged key by key, any other value in over replaces the one in base
+    debug_fn!(base,over);
+    for (key, value) in over {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(over_table)) => {
+                merge_table(base_table, over_table);
+            }
+            (_, value) => { base.insert(key, value); }
+        }
+    }
+}
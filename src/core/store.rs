@@ -0,0 +1,225 @@
+use std::collections::HashSet;
+use std::fs;
+use std::fs::{File, OpenOptions, Permissions};
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use colored::Colorize;
+use flate2::Compression as GzLevel;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use sha2::{Digest, Sha256};
+
+use crate::{debug_fn, debug_info, debugln};
+use crate::core::config::Compression;
+use crate::core::error::HinaError;
+use crate::core::error::HinaError::{DirCreateError, DirReadError, FileCopyError, FileNotExistError, FileOpenError, FileWriteError};
+use crate::core::func;
+use crate::core::global::{DEBUG, GZIP_EXT, STORE, ZSTD_EXT};
+
+// Content addressed store for the dedup bin, a regular file is kept once per
+// content at store/<first 2 hex>/<sha256>, with .zst or .gz added once compressed.
+
+pub fn get_store_path(data_path: &PathBuf) -> PathBuf {
+    // The store lives in the data dir whatever bin.path says, so the protect rules keep covering it
+    debug_fn!(data_path);
+    data_path.join(STORE)
+}
+
+pub fn blob_path(store: &PathBuf, hash: &str) -> PathBuf {
+    debug_fn!(store,hash);
+    let mut path = store.clone();
+    path.push(&hash[..2]);
+    path.push(hash);
+    path
+}
+
+fn compressed_path(path: &PathBuf, compression: Compression) -> PathBuf {
+    debug_fn!(path,compression);
+    match compression {
+        Compression::None => { path.clone() }
+        Compression::Zstd => { PathBuf::from(format!("{}.{}", path.display(), ZSTD_EXT)) }
+        Compression::Gzip => { PathBuf::from(format!("{}.{}", path.display(), GZIP_EXT)) }
+    }
+}
+
+pub fn find_blob(store: &PathBuf, hash: &str) -> Option<(PathBuf, Compression)> {
+    debug_fn!(store,hash);
+    let path = blob_path(store, hash);
+    for compression in [Compression::None, Compression::Zstd, Compression::Gzip] {
+        let candidate = compressed_path(&path, compression);
+        if fs::symlink_metadata(&candidate).is_ok() {
+            return Some((candidate, compression));
+        }
+    }
+    None
+}
+
+pub fn hash_file(path: &PathBuf) -> Result<String, HinaError> {
+    debug_fn!(path);
+    let mut file = match File::open(path) {
+        Ok(file) => { file }
+        Err(err) => { return Err(FileOpenError(format!("Unable to open {}: {}", path.display(), err))); }
+    };
+    let mut hasher = Sha256::new();
+    match io::copy(&mut file, &mut hasher) {
+        Ok(_) => { Ok(format!("{:x}", hasher.finalize())) }
+        Err(err) => { Err(FileOpenError(format!("Unable to read {}: {}", path.display(), err))) }
+    }
+}
+
+pub fn put(store: &PathBuf, target: &PathBuf) -> Result<String, HinaError> {
+    // Move target into the store, or just drop it when the same content is stored already
+    debug_fn!(store,target);
+    let hash = hash_file(target)?;
+    let path = blob_path(store, &hash);
+    if find_blob(store, &hash).is_some() {
+        debugln!("{} is stored already as {}", target.display(), hash);
+        func::remove_path(target)?;
+        return Ok(hash);
+    }
+    let dir = path.parent().unwrap().to_path_buf();
+    if fs::symlink_metadata(store).is_err() {
+        match fs::create_dir(store) {
+            Ok(_) => {}
+            Err(err) => { return Err(DirCreateError(format!("Unable to create {}: {}", store.display(), err))); }
+        }
+        match fs::set_permissions(store, Permissions::from_mode(0o700)) {
+            Ok(_) => {}
+            Err(err) => { return Err(DirCreateError(format!("Unable to set mode of {}: {}", store.display(), err))); }
+        }
+    }
+    match fs::create_dir_all(&dir) {
+        Ok(_) => {}
+        Err(err) => { return Err(DirCreateError(format!("Unable to create {}: {}", dir.display(), err))); }
+    }
+    func::move_path(target, &path)?;
+    Ok(hash)
+}
+
+pub fn take(store: &PathBuf, hash: &str, dst: &PathBuf, shared: bool) -> Result<(), HinaError> {
+    // Rebuild the file at dst, the blob is kept while other records still share it
+    debug_fn!(store,hash,dst,shared);
+    let (path, compression) = match find_blob(store, hash) {
+        Some(blob) => { blob }
+        None => { return Err(FileNotExistError(format!("Blob {} is missing from {}", hash, store.display()))); }
+    };
+    match (compression, shared) {
+        (Compression::None, false) => { return func::move_path(&path, dst); }
+        (Compression::None, true) => {
+            match fs::copy(&path, dst) {
+                Ok(_) => {}
+                Err(err) => { return Err(FileCopyError(format!("Unable to copy {} to {}: {}", path.display(), dst.display(), err))); }
+            }
+        }
+        (compression, _) => { decompress(&path, dst, compression)?; }
+    }
+    if !shared {
+        func::remove_path(&path)?;
+    }
+    Ok(())
+}
+
+pub fn release(store: &PathBuf, hash: &str) -> Result<u64, HinaError> {
+    // Delete a blob no record refers to anymore, returns the bytes freed
+    debug_fn!(store,hash);
+    match find_blob(store, hash) {
+        Some((path, _)) => {
+            let size = func::get_size(&path);
+            func::remove_path(&path)?;
+            Ok(size)
+        }
+        None => { Ok(0) }
+    }
+}
+
+pub fn find_orphans(store: &Path, referenced: &HashSet<String>) -> Result<Vec<PathBuf>, HinaError> {
+    // Blobs whose hash no record refers to, left by a crash between put and saving the record
+    // or by a failed release, and temp files of an interrupted compress
+    debug_fn!(store);
+    let mut orphans = Vec::new();
+    let dirs = match fs::read_dir(store) {
+        Ok(dirs) => { dirs }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => { return Ok(orphans); }
+        Err(err) => { return Err(DirReadError(format!("Unable to read {}: {}", store.display(), err))); }
+    };
+    for dir in dirs.flatten() {
+        let dir = dir.path();
+        if !dir.is_dir() {
+            orphans.push(dir);
+            continue;
+        }
+        let blobs = match fs::read_dir(&dir) {
+            Ok(blobs) => { blobs }
+            Err(err) => { return Err(DirReadError(format!("Unable to read {}: {}", dir.display(), err))); }
+        };
+        for blob in blobs.flatten() {
+            let name = blob.file_name().to_string_lossy().to_string();
+            let hash = name.split('.').next().unwrap_or_default();
+            if name.ends_with(".tmp") || !referenced.contains(hash) {
+                orphans.push(blob.path());
+            }
+        }
+    }
+    orphans.sort();
+    Ok(orphans)
+}
+
+pub fn compress(store: &PathBuf, hash: &str, compression: Compression) -> Result<(), HinaError> {
+    // Compress a plain blob through a temp file, so an interrupted run leaves the blob intact
+    debug_fn!(store,hash,compression);
+    let path = match find_blob(store, hash) {
+        Some((path, Compression::None)) if compression != Compression::None => { path }
+        _ => { return Ok(()); }
+    };
+    let dst = compressed_path(&path, compression);
+    let tmp = PathBuf::from(format!("{}.tmp", dst.display()));
+    let mut src_file = match File::open(&path) {
+        Ok(file) => { file }
+        Err(err) => { return Err(FileOpenError(format!("Unable to open {}: {}", path.display(), err))); }
+    };
+    let tmp_file = match OpenOptions::new().write(true).create(true).truncate(true).open(&tmp) {
+        Ok(file) => { file }
+        Err(err) => { return Err(FileOpenError(format!("Unable to open {}: {}", tmp.display(), err))); }
+    };
+    let written = match compression {
+        Compression::Zstd => { zstd::stream::copy_encode(&mut src_file, tmp_file, 0) }
+        _ => {
+            let mut encoder = GzEncoder::new(tmp_file, GzLevel::default());
+            io::copy(&mut src_file, &mut encoder).and_then(|_| encoder.finish()).map(|_| ())
+        }
+    };
+    match written.and_then(|_| fs::rename(&tmp, &dst)) {
+        Ok(_) => {}
+        Err(err) => {
+            let _ = fs::remove_file(&tmp);
+            return Err(FileWriteError(format!("Unable to compress {}: {}", path.display(), err)));
+        }
+    }
+    func::remove_path(&path)?;
+    Ok(())
+}
+
+fn decompress(path: &PathBuf, dst: &PathBuf, compression: Compression) -> Result<(), HinaError> {
+    debug_fn!(path,dst,compression);
+    let src_file = match File::open(path) {
+        Ok(file) => { file }
+        Err(err) => { return Err(FileOpenError(format!("Unable to open {}: {}", path.display(), err))); }
+    };
+    let mut dst_file = match OpenOptions::new().write(true).create_new(true).open(dst) {
+        Ok(file) => { file }
+        Err(err) => { return Err(FileOpenError(format!("Unable to create {}: {}", dst.display(), err))); }
+    };
+    let written = match compression {
+        Compression::Zstd => { zstd::stream::copy_decode(src_file, &mut dst_file) }
+        _ => { io::copy(&mut GzDecoder::new(src_file), &mut dst_file).map(|_| ()) }
+    };
+    match written {
+        Ok(_) => { Ok(()) }
+        Err(err) => {
+            let _ = fs::remove_file(dst);
+            Err(FileWriteError(format!("Unable to decompress {}: {}", path.display(), err)))
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use std::fs::File;
+use std::io::{Read, stdout, Write};
+
+use colored::Colorize;
+
+use crate::{debug_fn, debug_info, debugln};
+use crate::core::error::HinaError;
+use crate::core::error::HinaError::CommandExecError;
+use crate::core::func::execute_command;
+use crate::core::global::DEBUG;
+
+// Key input and in place redraw for interactive views like ps --watch. The tty is
+// switched by stty and drawn with ANSI escapes, so no terminal library is needed.
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Key {
+    Up,
+    Down,
+    Char(char),
+}
+
+#[derive(Debug)]
+pub struct RawTerminal {
+    tty: File,
+    saved: String,
+}
+
+impl RawTerminal {
+    pub fn open() -> Result<RawTerminal, HinaError> {
+        debug_fn!();
+        let tty = match File::open("/dev/tty") {
+            Ok(tty) => { tty }
+            Err(err) => { return Err(CommandExecError(format!("A terminal is required: {}", err))); }
+        };
+        let saved = execute_command(&String::from("stty -g < /dev/tty 2>/dev/null"))?.trim().to_string();
+        if saved.is_empty() {
+            return Err(CommandExecError(String::from("A terminal is required: unable to read its mode with stty")));
+        }
+        // No echo or line buffering, Ctrl-C comes in as a key, and a read waits 0.1s at most
+        execute_command(&String::from("stty -icanon -echo -isig min 0 time 1 < /dev/tty"))?;
+        // Switch to the alternate screen and hide the cursor
+        print!("\x1b[?1049h\x1b[?25l");
+        let _ = stdout().flush();
+        Ok(RawTerminal { tty, saved })
+    }
+
+    pub fn read_key(&mut self) -> Option<Key> {
+        // The pressed key, or None after 0.1s without input
+        debug_fn!();
+        let mut buf = [0u8; 8];
+        let len = self.tty.read(&mut buf).unwrap_or(0);
+        match &buf[..len] {
+            [] => { None }
+            [0x1b, b'[', b'A', ..] => { Some(Key::Up) }
+            [0x1b, b'[', b'B', ..] => { Some(Key::Down) }
+            [byte, ..] => { Some(Key::Char(*byte as char)) }
+        }
+    }
+
+    pub fn get_size(&self) -> (usize, usize) {
+        // Rows and columns of the terminal, 24x80 when stty can not tell
+        debug_fn!();
+        let output = execute_command(&String::from("stty size < /dev/tty 2>/dev/null")).unwrap_or_default();
+        let size: Vec<usize> = output.split_whitespace().filter_map(|x| x.parse().ok()).collect();
+        match size[..] {
+            [rows, cols] if rows > 0 && cols > 0 => { (rows, cols) }
+            _ => { (24, 80) }
+        }
+    }
+
+    pub fn draw(&self, lines: &[String]) {
+        // Redraw from the top left, clearing what is left of the last frame
+        debug_fn!();
+        let mut frame = String::from("\x1b[H");
+        frame += &lines.join("\x1b[K\n");
+        frame += "\x1b[K\x1b[J";
+        print!("{}", frame);
+        let _ = stdout().flush();
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        debug_fn!();
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = stdout().flush();
+        let command = format!("stty {} < /dev/tty", self.saved);
+        if let Err(err) = execute_command(&command) {
+            debugln!("Unable to restore the terminal: {:?}", err);
+        }
+    }
+}
@@ -0,0 +1,306 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::fs::{OpenOptions, Permissions};
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::PathBuf;
+
+use chrono::{DateTime, Local, NaiveDateTime};
+use colored::Colorize;
+
+use crate::{debug_fn, debug_info, debugln};
+use crate::core::config::RMRecord;
+use crate::core::error::HinaError;
+use crate::core::error::HinaError::{BadFileError, DirCreateError, DirReadError, FileCreateError, FileWriteError};
+use crate::core::func;
+use crate::core::global::{BATCH_ID, DEBUG, TIME_FORMAT, XDG_BATCH_KEY, XDG_TRASH, XDG_TRASH_INFO_EXT};
+
+// Support of the FreeDesktop.org Trash specification, so that files trashed by
+// hina and by file managers like Nautilus or Dolphin are visible to each other.
+
+fn encode_path(path: &str) -> String {
+    // Percent-encode everything except unreserved characters and '/'
+    debug_fn!(path);
+    let mut result = String::new();
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.~/".contains(&byte) {
+            result.push(byte as char);
+        } else {
+            result += &format!("%{:02X}", byte);
+        }
+    }
+    result
+}
+
+fn decode_path(path: &str) -> String {
+    debug_fn!(path);
+    let bytes = path.as_bytes();
+    let mut result = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let code = String::from_utf8_lossy(&bytes[i + 1..i + 3]).to_string();
+            if let Ok(byte) = u8::from_str_radix(&code, 16) {
+                result.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        result.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&result).to_string()
+}
+
+fn ensure_trash_dir(trash: &PathBuf) -> Result<(), HinaError> {
+    // A trash dir is private to its user and holds files/ and info/
+    debug_fn!(trash);
+    for sub in ["", "files", "info"] {
+        let mut dir = trash.clone();
+        if !sub.is_empty() {
+            dir.push(sub);
+        }
+        if fs::symlink_metadata(&dir).is_err() {
+            match fs::create_dir_all(&dir) {
+                Ok(_) => {}
+                Err(err) => { return Err(DirCreateError(format!("Unable to create {}: {}", dir.display(), err))); }
+            }
+            match fs::set_permissions(&dir, Permissions::from_mode(0o700)) {
+                Ok(_) => {}
+                Err(err) => { return Err(DirCreateError(format!("Unable to set mode of {}: {}", dir.display(), err))); }
+            }
+        }
+        match fs::symlink_metadata(&dir) {
+            Ok(meta) if meta.is_dir() => {}
+            _ => { return Err(BadFileError(format!("{} is not a directory", dir.display()))); }
+        }
+    }
+    Ok(())
+}
+
+pub fn get_home_trash() -> Result<PathBuf, HinaError> {
+    debug_fn!();
+    let mut trash = func::get_xdg_data_home()?;
+    trash.push(XDG_TRASH);
+    Ok(trash)
+}
+
+fn get_top_trashes(mount_point: &PathBuf, uid: &String) -> Vec<PathBuf> {
+    // Both the shared $topdir/.Trash/$uid and the per-user $topdir/.Trash-$uid are allowed by the spec
+    debug_fn!(mount_point,uid);
+    let mut shared = mount_point.clone();
+    shared.push(".Trash");
+    shared.push(uid);
+    let mut private = mount_point.clone();
+    private.push(format!(".Trash-{}", uid));
+    vec![shared, private]
+}
+
+pub fn get_all_trashes(uid: &String) -> Result<Vec<PathBuf>, HinaError> {
+    // The home trash and every existing trash on the top of a mount point
+    debug_fn!(uid);
+    let home_trash = get_home_trash()?;
+    let mut trashes = vec![home_trash.clone()];
+    for mount_point in func::get_mount_points()? {
+        for trash in get_top_trashes(&mount_point, uid) {
+            let mut info = trash.clone();
+            info.push("info");
+            if info.is_dir() && !trashes.contains(&trash) {
+                trashes.push(trash);
+            }
+        }
+    }
+    Ok(trashes)
+}
+
+pub fn locate_trash(target: &PathBuf, uid: &String) -> Result<PathBuf, HinaError> {
+    // Use the home trash when possible, otherwise $topdir/.Trash-$uid of the target's mount
+    debug_fn!(target,uid);
+    let home_trash = get_home_trash()?;
+    ensure_trash_dir(&home_trash)?;
+    let parent = match target.parent() {
+        Some(parent) => { parent.to_path_buf() }
+        None => { return Ok(home_trash); }
+    };
+    let parent_dev = match fs::metadata(&parent) {
+        Ok(meta) => { meta.dev() }
+        Err(err) => { return Err(BadFileError(format!("Unable to open {}: {}", parent.display(), err))); }
+    };
+    let home_dev = match fs::metadata(&home_trash) {
+        Ok(meta) => { meta.dev() }
+        Err(err) => { return Err(BadFileError(format!("Unable to open {}: {}", home_trash.display(), err))); }
+    };
+    if parent_dev == home_dev {
+        return Ok(home_trash);
+    }
+    let top_trash = match parent.canonicalize() {
+        Ok(canonical_parent) => {
+            let mount_point = func::get_mount_point(&canonical_parent)?;
+            get_top_trashes(&mount_point, uid).pop().unwrap()
+        }
+        Err(err) => { return Err(BadFileError(format!("Unable to open {}: {}", parent.display(), err))); }
+    };
+    match ensure_trash_dir(&top_trash) {
+        Ok(_) => { Ok(top_trash) }
+        Err(err) => {
+            debugln!("Unable to use {}, fall back to {}: {:?}", top_trash.display(), home_trash.display(), err);
+            Ok(home_trash)
+        }
+    }
+}
+
+pub fn reserve_name(trash: &PathBuf, target: &PathBuf, now: &DateTime<Local>) -> Result<String, HinaError> {
+    // Atomically create the .trashinfo to claim a free name in files/, as the spec requires
+    debug_fn!(trash,target);
+    let base_name = match target.file_name() {
+        Some(name) => { name.to_string_lossy().to_string() }
+        None => { return Err(BadFileError(format!("Unable to trash {}", target.display()))); }
+    };
+    // Other tools ignore unknown keys, the batch id lets rb --undo find files removed together
+    let content = format!("[Trash Info]\nPath={}\nDeletionDate={}\n{}={}\n",
+                          encode_path(&target.display().to_string()),
+                          now.format("%Y-%m-%dT%H:%M:%S"),
+                          XDG_BATCH_KEY,
+                          *BATCH_ID);
+    let mut index = 1;
+    loop {
+        let name = if index == 1 { base_name.clone() } else { format!("{}.{}", base_name, index) };
+        let mut file = trash.clone();
+        file.push("files");
+        file.push(&name);
+        let mut info = trash.clone();
+        info.push("info");
+        info.push(format!("{}.{}", name, XDG_TRASH_INFO_EXT));
+        index += 1;
+        if fs::symlink_metadata(&file).is_ok() {
+            continue;
+        }
+        match OpenOptions::new().write(true).create_new(true).open(&info) {
+            Ok(mut info_file) => {
+                match info_file.write_all(content.as_bytes()) {
+                    Ok(_) => {}
+                    Err(err) => { return Err(FileWriteError(format!("Unable to write {}: {}", info.display(), err))); }
+                }
+                return Ok(name);
+            }
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => { continue; }
+            Err(err) => { return Err(FileCreateError(format!("Unable to create {}: {}", info.display(), err))); }
+        }
+    }
+}
+
+pub fn info_path_of(file: &PathBuf) -> Option<PathBuf> {
+    // files/<name> is described by info/<name>.trashinfo
+    debug_fn!(file);
+    let name = file.file_name()?.to_string_lossy().to_string();
+    let mut info = file.parent()?.parent()?.to_path_buf();
+    info.push("info");
+    info.push(format!("{}.{}", name, XDG_TRASH_INFO_EXT));
+    Some(info)
+}
+
+fn parse_trash_info(trash: &PathBuf, info: &PathBuf) -> Option<RMRecord> {
+    debug_fn!(trash,info);
+    let content = fs::read_to_string(info).ok()?;
+    let mut path = None;
+    let mut deletion_date = String::new();
+    let mut batch = String::new();
+    for line in content.lines() {
+        if let Some(value) = line.strip_prefix("Path=") {
+            path = Some(decode_path(value.trim()));
+        } else if let Some(value) = line.strip_prefix("DeletionDate=") {
+            deletion_date = value.trim().to_string();
+        } else if let Some(value) = line.strip_prefix(&format!("{}=", XDG_BATCH_KEY)) {
+            batch = value.trim().to_string();
+        }
+    }
+    let mut src = PathBuf::from(path?);
+    if src.is_relative() {
+        // Relative paths are relative to the top directory holding the trash
+        let mut top = trash.parent()?.to_path_buf();
+        if !trash.file_name()?.to_string_lossy().starts_with(".Trash-") {
+            top = top.parent()?.to_path_buf();
+        }
+        top.push(src);
+        src = top;
+    }
+    let delete_time = match NaiveDateTime::parse_from_str(&deletion_date, "%Y-%m-%dT%H:%M:%S") {
+        Ok(time) => { time.format(TIME_FORMAT).to_string() }
+        Err(_) => { deletion_date }
+    };
+    let name = info.file_stem()?.to_string_lossy().to_string();
+    let mut bin = trash.clone();
+    bin.push("files");
+    let mut file = bin.clone();
+    file.push(name);
+    let mut record = RMRecord::from(
+        file.display().to_string(),
+        src.display().to_string(),
+        delete_time,
+        bin.display().to_string(),
+    );
+    record.set_batch(batch);
+    // The spec keeps no metadata, take it from the trashed file itself
+    if let Ok(meta) = fs::symlink_metadata(&file) {
+        let link_target = fs::read_link(&file).ok().map(|link| link.display().to_string());
+        record.set_meta(&meta, func::get_size(&file), link_target);
+    }
+    Some(record)
+}
+
+pub fn load_records(uid: &String) -> Result<Vec<RMRecord>, HinaError> {
+    // Build the recycle bin records from every .trashinfo, oldest first
+    debug_fn!(uid);
+    let mut records = Vec::new();
+    for trash in get_all_trashes(uid)? {
+        let mut info_dir = trash.clone();
+        info_dir.push("info");
+        if !info_dir.is_dir() {
+            continue;
+        }
+        let entries = match info_dir.read_dir() {
+            Ok(entries) => { entries }
+            Err(err) => { return Err(DirReadError(format!("Unable to read {}: {}", info_dir.display(), err))); }
+        };
+        for entry in entries.flatten() {
+            let info = entry.path();
+            if info.extension() != Some(OsStr::new(XDG_TRASH_INFO_EXT)) {
+                continue;
+            }
+            match parse_trash_info(&trash, &info) {
+                Some(record) => { records.push(record); }
+                None => { debugln!("Skip malformed {}", info.display()); }
+            }
+        }
+    }
+    records.sort_by(|x, y| x.get_del_time().cmp(y.get_del_time()));
+    Ok(records)
+}
+
+pub fn sync_records(uid: &String, rm_stack: &[RMRecord]) -> Result<(), HinaError> {
+    // Drop the .trashinfo of entries restored or emptied in this run, leaving the ones
+    // other tools may have added meanwhile untouched
+    debug_fn!(uid);
+    let files: Vec<&String> = rm_stack.iter().map(|record| record.get_file()).collect();
+    for trash in get_all_trashes(uid)? {
+        let mut info_dir = trash.clone();
+        info_dir.push("info");
+        let entries = match info_dir.read_dir() {
+            Ok(entries) => { entries }
+            Err(_) => { continue; }
+        };
+        for entry in entries.flatten() {
+            let info = entry.path();
+            if info.extension() != Some(OsStr::new(XDG_TRASH_INFO_EXT)) {
+                continue;
+            }
+            let mut file = trash.clone();
+            file.push("files");
+            file.push(info.file_stem().unwrap());
+            if !files.contains(&&file.display().to_string()) && fs::symlink_metadata(&file).is_err() {
+                func::remove_path(&info)?;
+            }
+        }
+    }
+    Ok(())
+}
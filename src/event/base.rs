@@ -1,9 +1,14 @@
 use std::path::PathBuf;
 
-use crate::core::config::{Flag, RMRecord};
+use crate::core::config::{Flag, FlagSpec, RMRecord};
 use crate::core::error::HinaError;
 
 pub trait HinaModuleRun {
+    // Flags the module accepts, anything else is rejected when parsing the command line
+    fn flag_specs(&self) -> Vec<FlagSpec> {
+        Vec::new()
+    }
+
     fn run(&self,
            _work_path: &PathBuf,
            _data_path: &PathBuf,
@@ -16,4 +21,18 @@ pub trait HinaModuleRun {
         let err = format!("Function run not implemented");
         Err(HinaError::NotImplementedError(err))
     }
+
+    // Modules that need all args at once handle them here and return true,
+    // otherwise run is called once per arg
+    fn run_batch(&self,
+                 _work_path: &PathBuf,
+                 _data_path: &PathBuf,
+                 _recycle_path: &PathBuf,
+                 _user: &String,
+                 _uid: &String,
+                 _flags: &Flag,
+                 _rm_stack: &mut Vec<RMRecord>,
+                 _args: &Vec<String>) -> Result<bool, HinaError> {
+        Ok(false)
+    }
 }
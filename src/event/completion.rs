@@ -0,0 +1,328 @@
+use std::path::PathBuf;
+
+use colored::Colorize;
+
+use crate::{debug_fn, debug_info};
+use crate::core::config::{Flag, FlagSpec, FlagType, RMRecord, TrashBackend};
+use crate::core::error::HinaError;
+use crate::core::error::HinaError::ConfigParseError;
+use crate::core::{func, xdg};
+use crate::core::global::{DEBUG, HELP_DICT, MODULE_MAP, TRASH_BACKEND};
+use crate::event::base::HinaModuleRun;
+use crate::event::holder::PlaceHold;
+use crate::event::process::Process;
+
+// Completion scripts are generated from MODULE_MAP, HELP_DICT and the flag declarations, the
+// recycle bin records and pids are asked from hina completions --list=KIND while completing.
+
+static COMPLETION_FLAGS: &[FlagSpec] = &[
+    FlagSpec {
+        choices: &["records", "pids"],
+        ..FlagSpec::new("list", &[], FlagType::Str, "KIND", "Print the candidates of dynamic completion, records or pids.")
+    },
+];
+
+static SHELLS: [&str; 3] = ["bash", "zsh", "fish"];
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Completion;
+
+impl HinaModuleRun for Completion {
+    fn flag_specs(&self) -> Vec<FlagSpec> {
+        COMPLETION_FLAGS.to_vec()
+    }
+
+    fn run(&self,
+           _work_path: &PathBuf,
+           _data_path: &PathBuf,
+           _recycle_path: &PathBuf,
+           _user: &String,
+           _uid: &String,
+           _flags: &Flag,
+           _rm_stack: &mut Vec<RMRecord>,
+           _arg: Option<&String>,
+    ) -> Result<(), HinaError> {
+        debug_fn!(_work_path,_data_path,_recycle_path,_user,_uid,_flags,_rm_stack,_arg);
+        let _help = _flags.parse_bool(vec!["help"]);
+        if _help {
+            Completion::print_help(_flags)?;
+            return Ok(());
+        }
+        let list = _flags.parse_string(vec!["list"]);
+        if !list.is_empty() {
+            Completion::print_candidates(_data_path, _uid, &list);
+            return Ok(());
+        }
+        let script = match _arg {
+            Some(shell) => { Completion::get_script(shell)? }
+            None => { return Err(ConfigParseError(String::from("Missing shell, expect bash, zsh or fish"))); }
+        };
+        print!("{}", script);
+        Ok(())
+    }
+}
+
+impl Completion {
+    pub fn generate(input: &[String]) -> Option<Result<String, HinaError>> {
+        // hina completions SHELL only needs the flag declarations, so it is answered before the
+        // settings, the data dir and the lock are set up, and make can run it without HOME or USER
+        debug_fn!(input);
+        match input {
+            [_, module, shell] if module == "completions" && !shell.starts_with('-') => { Some(Completion::get_script(shell)) }
+            _ => { None }
+        }
+    }
+
+    fn get_script(shell: &str) -> Result<String, HinaError> {
+        debug_fn!(shell);
+        match shell {
+            "bash" => { Ok(Completion::bash_script()) }
+            "zsh" => { Ok(Completion::zsh_script()) }
+            "fish" => { Ok(Completion::fish_script()) }
+            shell => { Err(ConfigParseError(format!("Unknown shell \'{}\', expect bash, zsh or fish", shell))) }
+        }
+    }
+
+    fn print_help(flags: &Flag) -> Result<(), HinaError> {
+        debug_fn!(flags);
+        func::print_help("completions", "[OPTION]... SHELL", flags)
+    }
+
+    fn print_candidates(data_path: &PathBuf, uid: &String, kind: &str) {
+        // One "candidate<TAB>description" per line, errors are left out so they never end up in a prompt
+        debug_fn!(data_path,uid,kind);
+        match kind {
+            "records" => {
+                // Read only, the stack file is replaced atomically so no lock is needed
+                let records = match *TRASH_BACKEND {
+                    TrashBackend::Hina => { func::load_rm_stack(data_path) }
+                    TrashBackend::Xdg => { xdg::load_records(uid) }
+                }.unwrap_or_default();
+                for (i, record) in records.iter().enumerate() {
+                    println!("{}\t{}", i, record.get_src());
+                }
+                for record in records.iter() {
+                    println!("{}\tremoved at {}", record.get_src(), record.get_del_time());
+                }
+            }
+            _ => {
+                for (pid, cmd) in Process::list_pids().unwrap_or_default() {
+                    println!("{}\t{}", pid, cmd);
+                }
+            }
+        }
+    }
+
+    fn get_modules() -> Vec<(&'static str, &'static str, Vec<FlagSpec>)> {
+        // Name, description and flags of every module, in the order of the top level help
+        debug_fn!();
+        HELP_DICT
+            .values()
+            .flat_map(|operations| operations.iter())
+            .filter(|(name, _)| MODULE_MAP.contains_key(*name))
+            .map(|(name, desc)| (*name, *desc, MODULE_MAP[*name].get_flag_specs()))
+            .collect()
+    }
+
+    fn get_arg_complete(specs: &[FlagSpec]) -> Vec<&FlagSpec> {
+        // Flags without value that switch the args to dynamic completion, like rb --restore
+        debug_fn!();
+        specs
+            .iter()
+            .filter(|spec| spec.kind == FlagType::Bool && !spec.complete.is_empty())
+            .collect()
+    }
+
+    fn bash_script() -> String {
+        debug_fn!();
+        let modules = Completion::get_modules();
+        let names: Vec<&str> = modules.iter().map(|(name, _, _)| *name).collect();
+        let top_flags: Vec<String> = PlaceHold.flag_specs().iter().flat_map(|spec| spec.get_names()).collect();
+        let mut value_cases = String::new();
+        let mut next_word_cases = Vec::new();
+        let mut module_cases = String::new();
+        for (name, _, specs) in modules.iter() {
+            for spec in specs.iter().filter(|spec| spec.kind != FlagType::Bool) {
+                let keys: Vec<String> = spec.get_names().iter().map(|flag| format!("\"{} {}\"", name, flag)).collect();
+                if matches!(spec.kind, FlagType::Str | FlagType::Uint) {
+                    next_word_cases.extend(keys.clone());
+                }
+                let action = match spec.complete {
+                    "files" => { String::from("COMPREPLY=($(compgen -f -- \"$cur\"))") }
+                    "dirs" => { String::from("COMPREPLY=($(compgen -d -- \"$cur\"))") }
+                    "" if spec.choices.is_empty() => { String::from("COMPREPLY=()") }
+                    "" => { format!("COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))", spec.choices.join(" ")) }
+                    kind => { format!("_hina_list {} \"$cur\"", kind) }
+                };
+                value_cases.push_str(&format!("        {}) {}; return ;;\n", keys.join("|"), action));
+            }
+            let flags: Vec<String> = specs.iter().flat_map(|spec| spec.get_names()).collect();
+            module_cases.push_str(&format!("        {})\n", name));
+            module_cases.push_str(&format!("            if [[ \"$cur\" == -* ]]; then COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return; fi\n", flags.join(" ")));
+            for spec in Completion::get_arg_complete(specs) {
+                let tests: Vec<String> = spec.get_names().iter().map(|flag| format!("\" $words \" == *\" {} \"*", flag)).collect();
+                module_cases.push_str(&format!("            if [[ {} ]]; then _hina_list {} \"$cur\"; return; fi\n", tests.join(" || "), spec.complete));
+            }
+            if *name == "completions" {
+                module_cases.push_str(&format!("            COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return\n", SHELLS.join(" ")));
+            }
+            module_cases.push_str("            ;;\n");
+        }
+
+        let mut script = String::from("# bash completion for hina, generated by hina completions bash\n\n");
+        script.push_str("_hina_list() {\n");
+        script.push_str("    local IFS=$'\\n'\n");
+        script.push_str("    COMPREPLY=($(compgen -W \"$(hina completions --list=\"$1\" 2>/dev/null | cut -f1)\" -- \"$2\"))\n");
+        script.push_str("}\n\n");
+        script.push_str("_hina() {\n");
+        script.push_str("    local cur=\"${COMP_WORDS[COMP_CWORD]}\" prev=\"${COMP_WORDS[COMP_CWORD-1]}\" flag=\"\"\n");
+        script.push_str("    local words=\"${COMP_WORDS[*]}\"\n");
+        script.push_str("    # '=' breaks words in bash, --flag=value arrives as --flag, = and value\n");
+        script.push_str("    if [ \"$cur\" = \"=\" ]; then\n        flag=\"$prev\"\n        cur=\"\"\n");
+        script.push_str("    elif [ \"$prev\" = \"=\" ]; then\n        flag=\"${COMP_WORDS[COMP_CWORD-2]}\"\n    fi\n");
+        script.push_str("    if [ \"$COMP_CWORD\" -eq 1 ]; then\n");
+        script.push_str(&format!("        COMPREPLY=($(compgen -W \"{} help {}\" -- \"$cur\"))\n", names.join(" "), top_flags.join(" ")));
+        script.push_str("        return\n    fi\n");
+        script.push_str("    local module=\"${COMP_WORDS[1]}\"\n");
+        script.push_str("    if [ \"$module\" = help ]; then\n");
+        script.push_str(&format!("        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n", names.join(" ")));
+        script.push_str("        return\n    fi\n");
+        script.push_str("    if [ -z \"$flag\" ]; then\n");
+        script.push_str(&format!("        case \"$module $prev\" in\n            {}) flag=\"$prev\" ;;\n        esac\n", next_word_cases.join("|")));
+        script.push_str("    fi\n");
+        script.push_str(&format!("    case \"$module $flag\" in\n{}    esac\n", value_cases));
+        script.push_str(&format!("    case \"$module\" in\n{}    esac\n", module_cases));
+        script.push_str("    COMPREPLY=($(compgen -f -- \"$cur\"))\n");
+        script.push_str("}\n\n");
+        script.push_str("complete -F _hina hina\n");
+        script
+    }
+
+    fn zsh_escape(text: &str) -> String {
+        debug_fn!(text);
+        text.replace('\'', "'\\''").replace('[', "\\[").replace(']', "\\]").replace(':', "\\:")
+    }
+
+    fn zsh_script() -> String {
+        debug_fn!();
+        let modules = Completion::get_modules();
+        let described: Vec<String> = modules
+            .iter()
+            .map(|(name, desc, _)| format!("'{}:{}'", name, Completion::zsh_escape(desc)))
+            .collect();
+        let mut module_cases = String::new();
+        for (name, _, specs) in modules.iter() {
+            let mut lines = Vec::new();
+            for spec in specs.iter() {
+                let help = Completion::zsh_escape(spec.help);
+                let action = match spec.complete {
+                    "files" => { String::from("_files") }
+                    "dirs" => { String::from("_files -/") }
+                    "" if spec.choices.is_empty() => { String::from(" ") }
+                    "" => { format!("({})", spec.choices.join(" ")) }
+                    kind => { format!("_hina_list {}", kind) }
+                };
+                for flag in spec.get_names() {
+                    // A single letter is left without its optional value, like -h for --help[=man]
+                    let line = match spec.kind {
+                        FlagType::Bool => { format!("'{}[{}]'", flag, help) }
+                        FlagType::OptStr | FlagType::OptUint if flag.len() == 2 => { format!("'{}[{}]'", flag, help) }
+                        FlagType::Str | FlagType::Uint => { format!("'{}=[{}]:{}:{}'", flag, help, spec.value, action) }
+                        FlagType::OptStr | FlagType::OptUint => { format!("'{}=-[{}]::{}:{}'", flag, help, spec.value, action) }
+                    };
+                    lines.push(line);
+                }
+            }
+            let mut arg_action = String::from("_files");
+            for spec in Completion::get_arg_complete(specs) {
+                arg_action = format!("{{if (( ${{words[(I)({})]}} )); then _hina_list {}; else {}; fi}}", spec.get_names().join("|"), spec.complete, arg_action);
+            }
+            if *name == "completions" {
+                lines.push(format!("'1:shell:({})'", SHELLS.join(" ")));
+            } else {
+                lines.push(format!("'*:arg:{}'", arg_action));
+            }
+            module_cases.push_str(&format!("        {})\n            _arguments -s \\\n                {}\n            ;;\n", name, lines.join(" \\\n                ")));
+        }
+
+        let mut script = String::from("#compdef hina\n# zsh completion for hina, generated by hina completions zsh\n\n");
+        script.push_str("_hina_list() {\n");
+        script.push_str("    local -a items\n");
+        script.push_str("    items=(${(f)\"$(hina completions --list=$1 2>/dev/null | sed 's/:/\\\\:/g; s/\\t/:/')\"})\n");
+        script.push_str("    _describe -t $1 $1 items\n");
+        script.push_str("}\n\n");
+        script.push_str("_hina() {\n");
+        script.push_str(&format!("    local -a modules\n    modules=({})\n", described.join(" ")));
+        script.push_str("    if (( CURRENT == 2 )); then\n");
+        script.push_str("        _describe -t modules 'hina module' modules\n");
+        script.push_str("        compadd -- help -h --help -v --version\n");
+        script.push_str("        return\n    fi\n");
+        script.push_str("    local module=${words[2]}\n");
+        script.push_str("    shift words\n    (( CURRENT-- ))\n");
+        script.push_str("    case $module in\n");
+        script.push_str("        help)\n            _describe -t modules 'hina module' modules\n            ;;\n");
+        script.push_str(&module_cases);
+        script.push_str("    esac\n");
+        script.push_str("}\n\n");
+        script.push_str("_hina \"$@\"\n");
+        script
+    }
+
+    fn fish_escape(text: &str) -> String {
+        debug_fn!(text);
+        text.replace('\\', "\\\\").replace('\'', "\\'")
+    }
+
+    fn fish_script() -> String {
+        debug_fn!();
+        let modules = Completion::get_modules();
+        let names: Vec<&str> = modules.iter().map(|(name, _, _)| *name).collect();
+        let mut script = String::from("# fish completion for hina, generated by hina completions fish\n\n");
+        for (name, desc, _) in modules.iter() {
+            script.push_str(&format!("complete -c hina -n __fish_use_subcommand -f -a {} -d '{}'\n", name, Completion::fish_escape(desc)));
+        }
+        script.push_str("complete -c hina -n __fish_use_subcommand -f -a help -d 'Show the help of a module'\n");
+        for spec in PlaceHold.flag_specs().iter() {
+            script.push_str(&format!("complete -c hina -n __fish_use_subcommand -s {} -l {} -d '{}'\n", spec.aliases[0], spec.name, Completion::fish_escape(spec.help)));
+        }
+        script.push_str(&format!("complete -c hina -n '__fish_seen_subcommand_from help' -f -a '{}'\n", names.join(" ")));
+        for (name, _, specs) in modules.iter() {
+            script.push('\n');
+            let condition = format!("-n '__fish_seen_subcommand_from {}'", name);
+            for spec in specs.iter() {
+                let mut options = Vec::new();
+                for flag in [spec.name].iter().chain(spec.aliases.iter()) {
+                    if flag.len() == 1 {
+                        options.push(format!("-s {}", flag));
+                    } else if *flag == spec.name {
+                        options.push(format!("-l {}", flag));
+                    } else {
+                        options.push(format!("-o {}", flag));
+                    }
+                }
+                match (spec.kind, spec.complete) {
+                    (FlagType::Str | FlagType::Uint, "files") => { options.push(String::from("-r -F")); }
+                    (FlagType::Str | FlagType::Uint, "dirs") => { options.push(String::from("-x -a '(__fish_complete_directories)'")); }
+                    (FlagType::Str | FlagType::Uint, "") if spec.choices.is_empty() => { options.push(String::from("-x")); }
+                    (FlagType::Str | FlagType::Uint, "") => { options.push(format!("-x -a '{}'", spec.choices.join(" "))); }
+                    (FlagType::Str | FlagType::Uint, kind) => { options.push(format!("-x -a '(hina completions --list={})'", kind)); }
+                    _ => {}
+                }
+                script.push_str(&format!("complete -c hina {} {} -d '{}'\n", condition, options.join(" "), Completion::fish_escape(spec.help)));
+            }
+            for spec in Completion::get_arg_complete(specs) {
+                let seen: Vec<String> = [spec.name]
+                    .iter()
+                    .chain(spec.aliases.iter())
+                    .map(|flag| if flag.len() == 1 { format!("-s {}", flag) } else if *flag == spec.name { flag.to_string() } else { format!("-o {}", flag) })
+                    .collect();
+                let condition = format!("__fish_seen_subcommand_from {}; and __fish_contains_opt {}", name, seen.join(" "));
+                script.push_str(&format!("complete -c hina -n '{}' -f -a '(hina completions --list={})'\n", condition, spec.complete));
+            }
+            if *name == "completions" {
+                script.push_str(&format!("complete -c hina {} -f -a '{}'\n", condition, SHELLS.join(" ")));
+            }
+        }
+        script
+    }
+}
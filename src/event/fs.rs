@@ -5,13 +5,35 @@ use std::path::PathBuf;
 use colored::Colorize;
 
 use crate::{debug_fn, debug_info, debugln};
-use crate::core::config::{Flag, RMRecord};
+use crate::core::config::{Flag, FlagSpec, FlagType, RMRecord};
 use crate::core::error::HinaError;
 use crate::core::error::HinaError::DirReadError;
-use crate::core::func::{execute_command, execute_command_in_terminal, get_execute_target, parse_path_or, split_and_remove_blank};
+use crate::core::func;
+use crate::core::func::{execute_command, get_execute_target, parse_path_or, split_and_remove_blank};
 use crate::core::global::{DEBUG, MAX_RECURSIVE_DEPTH};
 use crate::event::base::HinaModuleRun;
 
+static MAKE_NESTED_DIR_FLAGS: &[FlagSpec] = &[
+    FlagSpec::new("recursive", &["r"], FlagType::Bool, "", "Create nested directories for files in subdirectories too."),
+];
+
+static RENAME_FLAGS: &[FlagSpec] = &[
+    FlagSpec::new("input", &["i"], FlagType::Str, "PATTERN", "Rename files whose name matches PATTERN."),
+    FlagSpec::new("output", &["o"], FlagType::Str, "PATTERN", "Replace the matched part with PATTERN."),
+    FlagSpec::new("append", &["a"], FlagType::Str, "STRING", "Insert STRING into the name."),
+    FlagSpec::new("num", &["n"], FlagType::Uint, "POS", "Position to insert the --append string at, 0 for prefix."),
+    FlagSpec::new("recursive", &["r"], FlagType::Bool, "", "Rename files in subdirectories too."),
+    FlagSpec::new("symlink", &["s"], FlagType::Bool, "", "Rename symbolic links themselves."),
+    FlagSpec::new("dir", &["d"], FlagType::Bool, "", "Rename directories as well."),
+];
+
+static LINK_CONVERT_FLAGS: &[FlagSpec] = &[
+    FlagSpec::new("s2l", &[], FlagType::Bool, "", "Convert symbolic links to hard links."),
+    FlagSpec::new("l2s", &[], FlagType::Bool, "", "Convert hard links to symbolic links, searching sources in --input."),
+    FlagSpec { complete: "dirs", ..FlagSpec::new("input", &["i"], FlagType::Str, "PATH", "Where to search the sources of hard links.") },
+    FlagSpec::new("recursive", &["r"], FlagType::Bool, "", "Convert links in subdirectories too."),
+];
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct MakeNestedDir;
 
@@ -22,6 +44,10 @@ pub struct Rename;
 pub struct LinkConvert;
 
 impl HinaModuleRun for MakeNestedDir {
+    fn flag_specs(&self) -> Vec<FlagSpec> {
+        MAKE_NESTED_DIR_FLAGS.to_vec()
+    }
+
     fn run(&self,
            _work_path: &PathBuf,
            _data_path: &PathBuf,
@@ -35,7 +61,7 @@ impl HinaModuleRun for MakeNestedDir {
         debug_fn!(_work_path,_data_path,_recycle_path,_user,_uid,_flags,_rm_stack,_arg);
         let _help = _flags.parse_bool(vec!["help"]);
         if _help {
-            MakeNestedDir::print_help()?;
+            MakeNestedDir::print_help(_flags)?;
             return Ok(());
         }
         let recursive = _flags.parse_bool(vec!["r", "recursive"]);
@@ -47,10 +73,9 @@ impl HinaModuleRun for MakeNestedDir {
 }
 
 impl MakeNestedDir {
-    fn print_help() -> Result<(), HinaError> {
-        debug_fn!();
-        execute_command_in_terminal("man", vec!["hina-mkndir"])?;
-        Ok(())
+    fn print_help(flags: &Flag) -> Result<(), HinaError> {
+        debug_fn!(flags);
+        func::print_help("mkndir", "[OPTION]... [PATH]", flags)
     }
 
     fn make_nested_dir_recursive(cur_path: &PathBuf,
@@ -107,6 +132,10 @@ impl MakeNestedDir {
 }
 
 impl HinaModuleRun for Rename {
+    fn flag_specs(&self) -> Vec<FlagSpec> {
+        RENAME_FLAGS.to_vec()
+    }
+
     fn run(&self,
            _work_path: &PathBuf,
            _data_path: &PathBuf,
@@ -120,7 +149,7 @@ impl HinaModuleRun for Rename {
         debug_fn!(_work_path,_data_path,_recycle_path,_user,_uid,_flags,_rm_stack,_arg);
         let _help = _flags.parse_bool(vec!["help"]);
         if _help {
-            Rename::print_help()?;
+            Rename::print_help(_flags)?;
             return Ok(());
         }
         let in_str = _flags.parse_string(vec!["i", "input"]);
@@ -138,10 +167,9 @@ impl HinaModuleRun for Rename {
 }
 
 impl Rename {
-    fn print_help() -> Result<(), HinaError> {
-        debug_fn!();
-        execute_command_in_terminal("man", vec!["hina-rn"])?;
-        Ok(())
+    fn print_help(flags: &Flag) -> Result<(), HinaError> {
+        debug_fn!(flags);
+        func::print_help("rn", "[OPTION]... [PATH]", flags)
     }
 
     fn rename_string(name: &String,
@@ -244,6 +272,10 @@ impl Rename {
 }
 
 impl HinaModuleRun for LinkConvert {
+    fn flag_specs(&self) -> Vec<FlagSpec> {
+        LINK_CONVERT_FLAGS.to_vec()
+    }
+
     fn run(&self,
            _work_path: &PathBuf,
            _data_path: &PathBuf,
@@ -257,7 +289,7 @@ impl HinaModuleRun for LinkConvert {
         debug_fn!(_work_path,_data_path,_recycle_path,_user,_uid,_flags,_rm_stack,_arg);
         let _help = _flags.parse_bool(vec!["help"]);
         if _help {
-            LinkConvert::print_help()?;
+            LinkConvert::print_help(_flags)?;
             return Ok(());
         }
         let s2l = _flags.parse_bool(vec!["s2l"]);
@@ -277,10 +309,9 @@ impl HinaModuleRun for LinkConvert {
 }
 
 impl LinkConvert {
-    fn print_help() -> Result<(), HinaError> {
-        debug_fn!();
-        execute_command_in_terminal("man", vec!["hina-lc"])?;
-        Ok(())
+    fn print_help(flags: &Flag) -> Result<(), HinaError> {
+        debug_fn!(flags);
+        func::print_help("lc", "[OPTION]... [PATH]", flags)
     }
 
     fn symlink_to_link(filepath: &PathBuf, cur_path: &PathBuf) -> Result<(), HinaError> {
@@ -3,15 +3,28 @@ use std::path::PathBuf;
 use colored::Colorize;
 
 use crate::{debug_fn, debug_info};
-use crate::core::config::{Flag, RMRecord};
+use crate::core::config::{Flag, FlagSpec, FlagType, RMRecord};
 use crate::core::error::HinaError;
+use crate::core::func::execute_command_in_terminal;
 use crate::core::global::{DEBUG, HELP_DICT};
 use crate::event::base::HinaModuleRun;
 
+static HINA_FLAGS: &[FlagSpec] = &[
+    FlagSpec {
+        choices: &["man"],
+        ..FlagSpec::new("help", &["h"], FlagType::OptStr, "man", "Show this help, or the man page with --help=man.")
+    },
+    FlagSpec::new("version", &["v"], FlagType::Bool, "", "Show the version."),
+];
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct PlaceHold;
 
 impl HinaModuleRun for PlaceHold {
+    fn flag_specs(&self) -> Vec<FlagSpec> {
+        HINA_FLAGS.to_vec()
+    }
+
     fn run(&self,
            _work_path: &PathBuf,
            _data_path: &PathBuf,
@@ -26,7 +39,7 @@ impl HinaModuleRun for PlaceHold {
         let help = _flags.parse_bool(vec!["h", "help"]);
         let version = _flags.parse_bool(vec!["v", "version"]);
         if help {
-            PlaceHold::print_help()?;
+            PlaceHold::print_help(_flags)?;
             return Ok(());
         }
         if version {
@@ -38,16 +51,23 @@ impl HinaModuleRun for PlaceHold {
 }
 
 impl PlaceHold {
-    fn print_help() -> Result<(), HinaError> {
-        debug_fn!();
-        println!("Usage: hina [-v | --version] [-h | --help] <module> [<params>]");
+    fn print_help(flags: &Flag) -> Result<(), HinaError> {
+        debug_fn!(flags);
+        if flags.parse_string(vec!["help"]) == "man" {
+            match execute_command_in_terminal("man", vec!["hina"]) {
+                Ok(_) => { return Ok(()); }
+                Err(_) => { eprintln!("Man page hina is not available, showing the built-in help"); }
+            }
+        }
+        println!("Usage: hina [-v | --version] [-h | --help[=man]] <module> [<params>]");
+        println!("       hina help <module>");
         println!();
         println!("These are common Hina commands used in various situations:");
         println!();
         for (help_situation, operations) in &*HELP_DICT {
             println!("{}", help_situation);
             for (module, desc) in operations {
-                println!("\t{}\t{} (See also: hina {} --help)", module, desc, module);
+                println!("\t{}\t{} (See also: hina help {})", module, desc, module);
             }
             println!();
         }
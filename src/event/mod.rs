@@ -2,4 +2,5 @@ pub mod recycle;
 pub mod process;
 pub mod fs;
 pub mod base;
-pub mod holder;
\ No newline at end of file
+pub mod holder;
+pub mod completion;
\ No newline at end of file
@@ -1,30 +1,120 @@
+use std::cmp::{max, min};
 use std::collections::HashMap;
 use std::fs;
-use std::fs::File;
-use std::io::Read;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
 use std::path::PathBuf;
+use std::process;
 use std::string::ToString;
+use std::thread;
+use std::time::Instant;
 
+use chrono::{DateTime, Datelike, Local};
 use colored::Colorize;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
 
-use crate::{debug_fn, debug_info, debug_var};
-use crate::core::config::{Flag, RMRecord};
+use crate::{debug_fn, debug_info, debug_var, debugln};
+use crate::core::config::{Flag, FlagSpec, FlagType, RMRecord};
 use crate::core::error::HinaError;
-use crate::core::func::{execute_command, execute_command_in_terminal, get_execute_target, parse_path_or, print_info, split_and_remove_blank};
-use crate::core::global::{DEBUG, MEM_EXTRACT_RE};
+use crate::core::error::HinaError::{BadFileError, CommandParseError, ConfigParseError, DirReadError, FileOpenError, FileWriteError, ProcessNotFoundError};
+use crate::core::func;
+use crate::core::func::{execute_command, get_execute_target, parse_path_or, print_info, split_and_remove_blank};
+use crate::core::term::{Key, RawTerminal};
+use crate::core::global::{CLOCK_TICKS, DEBUG, DEFAULT_WATCH_INTERVAL, DIFF_MAX_MAPPINGS, LEAK_MIN_GROWTH, LEAK_MIN_R2, LEAK_MIN_SAMPLES, MEM_EXTRACT_RE, SECOND_FORMAT, TIME_FORMAT};
 use crate::event::base::HinaModuleRun;
 
+// -h is human readable here, help is --help only
+static PROCESS_FLAGS: &[FlagSpec] = &[
+    FlagSpec::new("input", &["i"], FlagType::Str, "PATTERN", "Only show processes whose command contains PATTERN."),
+    FlagSpec { complete: "pids", ..FlagSpec::new("track", &["t"], FlagType::Uint, "PID", "Show the ancestors of process PID.") },
+    FlagSpec::new("dump", &["d"], FlagType::Bool, "", "Dump the memory maps of all processes to PATH, ./proc by default."),
+    FlagSpec::new("xray", &["x", "x-ray"], FlagType::Bool, "", "Show Size, Swap, PSS and RSS of each process."),
+    FlagSpec {
+        choices: &["pid", "size", "swap", "pss", "rss", "dirty", "name"],
+        ..FlagSpec::new("sort-by", &["s"], FlagType::Str, "FIELD", "With --xray, sort by pid, size, swap, pss or rss, with --maps by size, rss, pss, swap, dirty or name.")
+    },
+    FlagSpec::new("human-readable", &["h"], FlagType::Bool, "", "With --xray or --maps, print sizes in human readable units."),
+    FlagSpec::new("watch", &["w"], FlagType::OptStr, "INTERVAL", "Refresh the --xray table every INTERVAL, 2s by default, with keys to sort and signal processes."),
+    FlagSpec { complete: "pids", ..FlagSpec::new("record", &["r"], FlagType::Str, "PID|PATTERN", "Sample the memory of process PID, or of the processes matching PATTERN, into --out.") },
+    FlagSpec { default: "5s", ..FlagSpec::new("interval", &[], FlagType::Str, "INTERVAL", "With --record, the time between samples.") },
+    FlagSpec { default: "mem.jsonl", complete: "files", ..FlagSpec::new("out", &["o"], FlagType::Str, "FILE", "With --record, the file the samples are appended to.") },
+    FlagSpec::new("count", &["n"], FlagType::Uint, "N", "With --record, stop after N samples instead of running until interrupted."),
+    FlagSpec::new("mappings", &["m"], FlagType::Bool, "", "With --record, also save the usage of every mapping."),
+    FlagSpec { complete: "pids", ..FlagSpec::new("maps", &[], FlagType::Uint, "PID", "Show the memory of process PID by mapping, grouped by backing file and type.") },
+    FlagSpec { complete: "dirs", ..FlagSpec::new("load", &["l"], FlagType::Str, "DIR", "Show the --xray table of a DIR written by --dump.") },
+    FlagSpec::new("diff", &[], FlagType::Bool, "", "Compare two directories written by --dump, given as the arguments, by process and by mapping."),
+    FlagSpec { complete: "files", ..FlagSpec::new("analyze", &["a"], FlagType::Str, "FILE", "Report the memory growth of the processes in a --record FILE with a leak verdict.") },
+];
+
+// Sort keys cycled by 's' in --watch, memory is sorted largest first
+static WATCH_SORT_KEYS: &[&str] = &["pss", "rss", "swap", "size", "pid"];
+
 #[derive(Debug, Clone)]
-struct ProcessInfo {
+pub struct ProcessInfo {
+    _user: String,
     _uid: String,
     _pid: usize,
     _ppid: usize,
     _c: usize,
+    _state: String,
+    _threads: usize,
+    _start_time: u64,
     _stime: String,
     _tty: String,
     _time: String,
+    _argv: Vec<String>,
+    _cmd: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProcessMemUsage {
+    _user: String,
+    _pid: usize,
+    _cmd: String,
+    // Size, Swap, PSS and RSS in kB
+    _mem: [u64; 4],
+    // Change since the last sample, None for a new process
+    _delta: Option<[i64; 4]>,
+}
+
+// One line of a --record file, the totals and optionally each mapping of a process
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MemSample {
+    time: String,
+    pid: usize,
+    cmd: String,
+    total: HashMap<String, u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    maps: Vec<MapSample>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MapSample {
+    start: String,
+    end: String,
+    mode: String,
+    name: String,
+    kb: HashMap<String, usize>,
+}
+
+// A process with its memory maps, read from /proc or from a --dump directory
+#[derive(Debug, Clone)]
+pub struct ProcessMemDetail {
+    _user: String,
+    _pid: usize,
     _cmd: String,
-    _origin: String,
+    _map: ProcessMap,
+}
+
+// Mappings of the same backing file, or of the same type in the summary of --maps
+#[derive(Debug, Clone)]
+struct MapGroup {
+    kind: &'static str,
+    name: String,
+    count: usize,
+    // Size, RSS, PSS, Swap and Private_Dirty in kB
+    kb: [u64; 5],
 }
 
 #[derive(Debug, Clone)]
@@ -50,30 +140,378 @@ pub struct ProcessMap {
 pub struct Process;
 
 impl ProcessInfo {
-    pub fn from(input: &str) -> ProcessInfo {
-        debug_fn!(input);
-        let entries: Vec<String> = input
-            .split(" ")
-            .map(|s| s.to_string())
-            .filter(|s| !s.is_empty())
+    pub fn read(pid: usize,
+                users: &HashMap<String, String>,
+                boot_time: u64,
+                uptime: f64) -> Result<ProcessInfo, HinaError> {
+        // Read /proc/<pid>/stat, status and cmdline, fails when the process is gone
+        debug_fn!(pid,boot_time,uptime);
+        let stat = read_proc_file(pid, "stat")?;
+        let status = read_proc_file(pid, "status")?;
+        let cmdline = read_proc_file(pid, "cmdline")?;
+
+        // The command name may contain spaces and ')', so split at the last ')'
+        let (head, tail) = match stat.rsplit_once(')') {
+            Some(split) => { split }
+            None => { return Err(CommandParseError(format!("Bad /proc/{}/stat: {}", pid, stat.trim()))); }
+        };
+        let comm = head.split_once('(').map(|(_, comm)| comm).unwrap_or("");
+        let fields: Vec<&str> = tail.split_whitespace().collect();
+        if fields.len() < 20 {
+            return Err(CommandParseError(format!("Bad /proc/{}/stat: {}", pid, stat.trim())));
+        }
+        let field = |i: usize| -> u64 { fields[i].parse().unwrap_or(0) };
+        let cpu_ticks = field(11) + field(12);
+        let start_ticks = field(19);
+
+        // Effective uid, as ps -ef shows
+        let uid = status
+            .lines()
+            .find_map(|line| line.strip_prefix("Uid:"))
+            .and_then(|ids| ids.split_whitespace().nth(1))
+            .unwrap_or("")
+            .to_string();
+        let user = users.get(&uid).cloned().unwrap_or(uid.clone());
+
+        let argv: Vec<String> = cmdline
+            .split('\0')
+            .filter(|arg| !arg.is_empty())
+            .map(|arg| arg.to_string())
             .collect();
-        let mut cmd = String::new();
-        for cmd_part in &entries[7..] {
-            cmd += cmd_part;
-            cmd += " ";
-        }
-        cmd = cmd.trim().to_string();
-        return ProcessInfo {
-            _uid: entries[0].clone(),
-            _pid: entries[1].parse().unwrap(),
-            _ppid: entries[2].parse().unwrap(),
-            _c: entries[3].parse().unwrap(),
-            _stime: entries[4].clone(),
-            _tty: entries[5].clone(),
-            _time: entries[6].clone(),
+        // Kernel threads have no argv
+        let cmd = if argv.is_empty() { format!("[{}]", comm) } else { argv.join(" ") };
+
+        let elapsed = uptime - start_ticks as f64 / CLOCK_TICKS as f64;
+        let c = if elapsed > 0.0 {
+            (cpu_ticks as f64 / CLOCK_TICKS as f64 * 100.0 / elapsed) as usize
+        } else { 0 };
+        let start_time = boot_time + start_ticks / CLOCK_TICKS;
+
+        Ok(ProcessInfo {
+            _user: user,
+            _uid: uid,
+            _pid: pid,
+            _ppid: field(1) as usize,
+            _c: c,
+            _state: fields[0].to_string(),
+            _threads: field(17) as usize,
+            _start_time: start_time,
+            _stime: format_start_time(start_time),
+            _tty: format_tty(field(4)),
+            _time: format_cpu_time(cpu_ticks / CLOCK_TICKS),
+            _argv: argv,
             _cmd: cmd,
-            _origin: String::from(input),
+        })
+    }
+
+    pub fn from(input: &str) -> Result<ProcessInfo, HinaError> {
+        // Fallback parser for a line of ps -ef, used when /proc can not be read
+        debug_fn!(input);
+        let mut fields: Vec<&str> = Vec::new();
+        let mut rest = input.trim();
+        while fields.len() < 7 {
+            match rest.find(char::is_whitespace) {
+                Some(end) => {
+                    fields.push(&rest[..end]);
+                    rest = rest[end..].trim_start();
+                }
+                None => { return Err(CommandParseError(format!("Bad ps output: {}", input))); }
+            }
+        }
+        let (pid, ppid, c) = match (fields[1].parse(), fields[2].parse(), fields[3].parse()) {
+            (Ok(pid), Ok(ppid), Ok(c)) => { (pid, ppid, c) }
+            _ => { return Err(CommandParseError(format!("Bad ps output: {}", input))); }
+        };
+        // The command keeps its own spacing, argv is only a guess
+        Ok(ProcessInfo {
+            _user: fields[0].to_string(),
+            _uid: fields[0].to_string(),
+            _pid: pid,
+            _ppid: ppid,
+            _c: c,
+            _state: String::from("-"),
+            _threads: 0,
+            _start_time: 0,
+            _stime: fields[4].to_string(),
+            _tty: fields[5].to_string(),
+            _time: fields[6].to_string(),
+            _argv: rest.split_whitespace().map(|arg| arg.to_string()).collect(),
+            _cmd: rest.to_string(),
+        })
+    }
+
+    pub fn belongs_to(&self, user: &String, uid: &String) -> bool {
+        debug_fn!(user,uid);
+        &self._user == user || &self._uid == uid
+    }
+
+    pub fn get_pid(&self) -> usize {
+        debug_fn!();
+        self._pid
+    }
+
+    pub fn get_cmd(&self) -> &String {
+        debug_fn!();
+        &self._cmd
+    }
+
+    fn to_row(&self) -> Vec<String> {
+        debug_fn!();
+        let threads = if self._threads == 0 { String::from("-") } else { self._threads.to_string() };
+        vec![self._user.clone(),
+             self._pid.to_string(),
+             self._ppid.to_string(),
+             self._c.to_string(),
+             self._state.clone(),
+             threads,
+             self._stime.clone(),
+             self._tty.clone(),
+             self._time.clone(),
+             self._cmd.clone()]
+    }
+}
+
+fn read_proc_file(pid: usize, name: &str) -> Result<String, HinaError> {
+    debug_fn!(pid,name);
+    let path = format!("/proc/{}/{}", pid, name);
+    match fs::read(&path) {
+        Ok(content) => { Ok(String::from_utf8_lossy(&content).to_string()) }
+        Err(err) => { Err(FileOpenError(format!("Unable to read {}: {}", path, err))) }
+    }
+}
+
+fn format_start_time(start_time: u64) -> String {
+    // Like ps, HH:MM for today, MonDD for this year and the year otherwise
+    debug_fn!(start_time);
+    let start = match DateTime::from_timestamp(start_time as i64, 0) {
+        Some(start) => { start.with_timezone(&Local) }
+        None => { return String::from("?"); }
+    };
+    let now = Local::now();
+    if start.date_naive() == now.date_naive() {
+        start.format("%H:%M").to_string()
+    } else if start.year() == now.year() {
+        start.format("%b%d").to_string()
+    } else {
+        start.format("%Y").to_string()
+    }
+}
+
+fn format_tty(tty_nr: u64) -> String {
+    // tty_nr packs the device numbers as minor 0-7 and 20-31, major 8-19
+    debug_fn!(tty_nr);
+    let major = (tty_nr >> 8) & 0xfff;
+    let minor = (tty_nr & 0xff) | ((tty_nr >> 12) & 0xfff00);
+    match major {
+        0 => { String::from("?") }
+        4 if minor < 64 => { format!("tty{}", minor) }
+        4 => { format!("ttyS{}", minor - 64) }
+        136..=143 => { format!("pts/{}", (major - 136) * 256 + minor) }
+        _ => { format!("{}:{}", major, minor) }
+    }
+}
+
+fn format_cpu_time(seconds: u64) -> String {
+    debug_fn!(seconds);
+    let days = seconds / 86400;
+    let clock = format!("{:02}:{:02}:{:02}", seconds % 86400 / 3600, seconds % 3600 / 60, seconds % 60);
+    if days > 0 { format!("{}-{}", days, clock) } else { clock }
+}
+
+fn parse_interval(interval: &String) -> Result<(String, std::time::Duration), HinaError> {
+    // Intervals like 5s or 1m of at least a second, a bare number is taken as seconds
+    debug_fn!(interval);
+    let interval_str = if interval.chars().all(|c| c.is_ascii_digit()) {
+        format!("{}s", interval)
+    } else {
+        interval.clone()
+    };
+    match func::parse_duration(&interval_str)?.to_std() {
+        Ok(duration) if duration.as_secs() > 0 => { Ok((interval_str, duration)) }
+        _ => { Err(ConfigParseError(format!("Interval \'{}\' must be at least 1s", interval))) }
+    }
+}
+
+fn format_kb(kb: u64, human_readable: bool) -> String {
+    debug_fn!(kb,human_readable);
+    if human_readable { func::format_size(kb * 1024) } else { format!("{} KB", kb) }
+}
+
+fn format_kb_delta(delta: i64, human_readable: bool) -> String {
+    debug_fn!(delta,human_readable);
+    match delta {
+        0 => { String::from("0") }
+        delta if delta > 0 => { format!("+{}", format_kb(delta as u64, human_readable)) }
+        delta => { format!("-{}", format_kb(delta.unsigned_abs(), human_readable)) }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LeakVerdict {
+    TooFewSamples,
+    Likely,
+    Possible,
+    NoLeak,
+}
+
+pub fn linear_fit(points: &Vec<(f64, f64)>) -> (f64, f64) {
+    // Least squares slope of y over x and its r squared, which is 0 for a flat line
+    debug_fn!(points);
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let sxy: f64 = points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+    let sxx: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+    let syy: f64 = points.iter().map(|(_, y)| (y - mean_y).powi(2)).sum();
+    if sxx == 0.0 {
+        return (0.0, 0.0);
+    }
+    let r2 = if syy == 0.0 { 0.0 } else { sxy * sxy / (sxx * syy) };
+    (sxy / sxx, r2)
+}
+
+pub fn judge_leak(samples: usize, growth: f64, slope: f64, r2: f64) -> LeakVerdict {
+    // growth is the share PSS+Swap grew by, slope and r2 come from linear_fit
+    debug_fn!(samples,growth,slope,r2);
+    if samples < LEAK_MIN_SAMPLES {
+        LeakVerdict::TooFewSamples
+    } else if slope > 0.0 && growth >= LEAK_MIN_GROWTH && r2 >= LEAK_MIN_R2 {
+        LeakVerdict::Likely
+    } else if slope > 0.0 && growth >= LEAK_MIN_GROWTH {
+        LeakVerdict::Possible
+    } else {
+        LeakVerdict::NoLeak
+    }
+}
+
+fn read_users() -> HashMap<String, String> {
+    // Uid to user name from /etc/passwd, empty when it can not be read
+    debug_fn!();
+    let content = fs::read_to_string("/etc/passwd").unwrap_or_default();
+    // Reversed so the first entry of a shared uid wins
+    content
+        .lines()
+        .rev()
+        .filter_map(|line| {
+            let entries: Vec<&str> = line.split(':').collect();
+            if entries.len() > 2 { Some((entries[2].to_string(), entries[0].to_string())) } else { None }
+        })
+        .collect()
+}
+
+impl ProcessMemUsage {
+    pub fn from(user: &String, pid: usize, cmd: &String, mem: [u64; 4]) -> ProcessMemUsage {
+        debug_fn!(user,pid,cmd,mem);
+        ProcessMemUsage { _user: user.clone(), _pid: pid, _cmd: cmd.clone(), _mem: mem, _delta: None }
+    }
+}
+
+impl MemSample {
+    fn from(time: &String, pid: usize, cmd: &String, proc_map: &ProcessMap, with_maps: bool) -> MemSample {
+        debug_fn!(time,pid,cmd,with_maps);
+        let maps = if with_maps {
+            proc_map._data
+                .iter()
+                .map(|meta| MapSample {
+                    start: meta._start.clone(),
+                    end: meta._end.clone(),
+                    mode: meta._mode.clone(),
+                    name: meta._name.clone(),
+                    kb: meta._maps.clone(),
+                })
+                .collect()
+        } else { Vec::new() };
+        MemSample { time: time.clone(), pid, cmd: cmd.clone(), total: proc_map._total.clone(), maps }
+    }
+
+    fn get_total(&self, key: &str) -> u64 {
+        // pss+swap is the footprint, which still counts memory pushed out to swap
+        debug_fn!(key);
+        match key {
+            "pss+swap" => { self.get_total("pss") + self.get_total("swap") }
+            key => { self.total.get(key).copied().unwrap_or(0) }
+        }
+    }
+
+    fn get_map_totals(&self, key: &str) -> HashMap<String, u64> {
+        // Usage summed by mapping name, with anonymous mappings taken together
+        debug_fn!(key);
+        let mut totals: HashMap<String, u64> = HashMap::new();
+        for map in &self.maps {
+            let name = if map.name.is_empty() { String::from("[anon]") } else { map.name.clone() };
+            *totals.entry(name).or_insert(0) += map.kb.get(key).copied().unwrap_or(0) as u64;
+        }
+        totals
+    }
+}
+
+impl MapGroup {
+    fn add(&mut self, meta: &ProcessMapMeta) {
+        debug_fn!(meta);
+        self.count += 1;
+        for (i, key) in ["size", "rss", "pss", "swap", "private_dirty"].iter().enumerate() {
+            self.kb[i] += meta._maps.get(*key).copied().unwrap_or(0) as u64;
+        }
+    }
+
+    fn merge(&mut self, other: &MapGroup) {
+        debug_fn!(other);
+        self.count += other.count;
+        for i in 0..self.kb.len() {
+            self.kb[i] += other.kb[i];
+        }
+    }
+
+    fn sort(groups: &mut [MapGroup], sort_by: &str) {
+        // Largest first, by RSS unless another size is given, or by name
+        debug_fn!(sort_by);
+        let ind = match sort_by {
+            "name" => {
+                groups.sort_by(|x1, x2| x1.kind.cmp(x2.kind).then(x1.name.cmp(&x2.name)));
+                return;
+            }
+            "size" => { 0 }
+            "pss" => { 2 }
+            "swap" => { 3 }
+            "dirty" => { 4 }
+            _ => { 1 }
         };
+        groups.sort_by(|x1, x2| x2.kb[ind].cmp(&x1.kb[ind]).then(x1.name.cmp(&x2.name)));
+    }
+
+    fn to_row(&self, human_readable: bool) -> Vec<String> {
+        debug_fn!(human_readable);
+        let mut row = vec![self.kind.to_string(), self.count.to_string()];
+        row.extend(self.kb.iter().map(|kb| format_kb(*kb, human_readable)));
+        row.push(self.name.clone());
+        row
+    }
+}
+
+fn group_maps(proc_map: &ProcessMap) -> IndexMap<(&'static str, String), MapGroup> {
+    // Mappings summed by backing file, anonymous ones taken together
+    debug_fn!();
+    let mut groups: IndexMap<(&str, String), MapGroup> = IndexMap::new();
+    for meta in &proc_map._data {
+        let kind = get_map_kind(&meta._name);
+        let name = if meta._name.is_empty() { String::from("[anon]") } else { meta._name.clone() };
+        groups.entry((kind, name.clone())).or_insert(MapGroup { kind, name, count: 0, kb: [0; 5] }).add(meta);
+    }
+    groups
+}
+
+fn get_map_kind(name: &str) -> &'static str {
+    // Type of a mapping by its name, a shared library is a file named like *.so or *.so.1
+    debug_fn!(name);
+    let file_name = name.rsplit('/').next().unwrap_or(name);
+    match name {
+        "" => { "anon" }
+        "[heap]" => { "heap" }
+        name if name.starts_with("[stack") => { "stack" }
+        name if name.starts_with("[anon") => { "anon" }
+        name if name.starts_with('[') => { "special" }
+        _ if file_name.ends_with(".so") || file_name.contains(".so.") => { "lib" }
+        _ => { "file" }
     }
 }
 
@@ -81,6 +519,9 @@ impl ProcessMapMeta {
     pub fn from(smap_block: &Vec<&str>, cmdline: &String) -> Result<ProcessMapMeta, HinaError> {
         debug_fn!(smap_block,cmdline);
         let keys = split_and_remove_blank(&smap_block[0].to_string(), " ")?;
+        if keys.len() < 5 || keys[0].split("-").count() != 2 {
+            return Err(BadFileError(format!("Bad mapping \'{}\'", smap_block[0])));
+        }
         let mut maps: HashMap<String, usize> = HashMap::new();
         for line in smap_block {
             let caps = MEM_EXTRACT_RE.captures(line);
@@ -93,7 +534,7 @@ impl ProcessMapMeta {
         }
 
         let range: Vec<&str> = keys[0].split("-").collect();
-        let name = if keys.len() > 5 { keys[5].to_string() } else { "".to_string() };
+        let name = if keys.len() > 5 { keys[5..].join(" ") } else { "".to_string() };
         Ok(ProcessMapMeta {
             _start: range[0].to_string(),
             _end: range[1].to_string(),
@@ -109,36 +550,44 @@ impl ProcessMapMeta {
 }
 
 impl ProcessMap {
-    pub fn from(input: Vec<ProcessMapMeta>) -> ProcessMap {
+    pub fn from(input: Vec<ProcessMapMeta>) -> Result<ProcessMap, HinaError> {
         debug_fn!(input);
-        let keys = input[0]._maps.keys();
+        let keys = match input.first() {
+            Some(first) => { first._maps.keys() }
+            None => { return Err(BadFileError(String::from("No memory mapping found in smaps"))); }
+        };
         let mut total: HashMap<String, u64> = HashMap::new();
         for key in keys {
             let mut cal: u64 = 0;
             for datum in &input {
-                cal += datum._maps[key] as u64;
+                cal += datum._maps.get(key).copied().unwrap_or(0) as u64;
             }
             total.insert(key.clone(), cal);
         }
-        return ProcessMap {
+        Ok(ProcessMap {
             _data: input,
             _total: total,
-        };
+        })
+    }
+
+    pub fn get_total(&self, key: &str) -> u64 {
+        debug_fn!(key);
+        self._total.get(key).copied().unwrap_or(0)
     }
 
     pub fn get_total_as_kb(&self, key: &str) -> String {
         debug_fn!(key);
-        return format!("{} KB", self._total[&key.to_string()]);
+        format!("{} KB", self.get_total(key))
     }
 
     pub fn get_total_as_str(&self, key: &str) -> String {
         debug_fn!(key);
-        return format!("{}", self._total[&key.to_string()]);
+        format!("{}", self.get_total(key))
     }
 
     pub fn get_total_as_human_readable(&self, key: &str) -> String {
         debug_fn!(key);
-        let mut num = self._total[&key.to_string()] as f64;
+        let mut num = self.get_total(key) as f64;
         if num > 1024f64 {
             num /= 1024.0;
         } else {
@@ -154,6 +603,10 @@ impl ProcessMap {
 }
 
 impl HinaModuleRun for Process {
+    fn flag_specs(&self) -> Vec<FlagSpec> {
+        PROCESS_FLAGS.to_vec()
+    }
+
     fn run(&self,
            _work_path: &PathBuf,
            _data_path: &PathBuf,
@@ -167,7 +620,7 @@ impl HinaModuleRun for Process {
         debug_fn!(_work_path,_data_path,_recycle_path,_user,_uid,_flags,_rm_stack,_arg);
         let _help = _flags.parse_bool(vec!["help"]);
         if _help {
-            Process::print_help()?;
+            Process::print_help(_flags)?;
             return Ok(());
         }
         let spec_pattern = _flags.parse_string(vec!["i", "input"]);
@@ -176,6 +629,20 @@ impl HinaModuleRun for Process {
         let xray = _flags.parse_bool(vec!["x", "xray"]);
         let sort_by = _flags.parse_string(vec!["s", "sort-by"]);
         let human_readable = _flags.parse_bool(vec!["h", "human-readable"]);
+        let watch = _flags.parse_bool(vec!["w", "watch"]);
+        let record = _flags.parse_string(vec!["r", "record"]);
+        let analyze = _flags.parse_string(vec!["a", "analyze"]);
+        let maps_id = _flags.parse_uint(vec!["maps"]);
+        let load = _flags.parse_string(vec!["l", "load"]);
+        if !load.is_empty() {
+            let dump = get_execute_target(_work_path, &PathBuf::from(load))?;
+            Process::show_dump(&dump, &spec_pattern, &sort_by, human_readable)?;
+            return Ok(());
+        }
+        if maps_id != 0 {
+            Process::show_proc_maps(maps_id, &sort_by, human_readable)?;
+            return Ok(());
+        }
         if ans_id != 0 {
             Process::show_process_ancestor(ans_id)?;
             return Ok(());
@@ -185,6 +652,28 @@ impl HinaModuleRun for Process {
             Process::dump_proc(_user, _uid, &mut target)?;
             return Ok(());
         }
+        if !record.is_empty() {
+            let interval = _flags.parse_string(vec!["interval"]);
+            let out = func::absolute_path(_work_path, &PathBuf::from(_flags.parse_string(vec!["o", "out"])));
+            let count = _flags.parse_uint(vec!["n", "count"]);
+            let with_maps = _flags.parse_bool(vec!["m", "mappings"]);
+            Process::record_proc_mem(_user, _uid, &record, &interval, &out, count, with_maps)?;
+            return Ok(());
+        }
+        if !analyze.is_empty() {
+            let input = get_execute_target(_work_path, &PathBuf::from(analyze))?;
+            Process::analyze_proc_mem(&input, human_readable)?;
+            return Ok(());
+        }
+        if watch {
+            // The interval is given as --watch=2s or as the argument
+            let mut interval = _flags.parse_string(vec!["w", "watch"]);
+            if interval.is_empty() {
+                interval = _arg.cloned().unwrap_or(String::from(DEFAULT_WATCH_INTERVAL));
+            }
+            Process::watch_proc_mem(_user, _uid, &spec_pattern, &sort_by, human_readable, &interval)?;
+            return Ok(());
+        }
         if xray {
             Process::get_proc_mem_detail(_user, _uid, &sort_by, human_readable)?;
             return Ok(());
@@ -196,31 +685,114 @@ impl HinaModuleRun for Process {
         }
         Ok(())
     }
+
+    fn run_batch(&self,
+                 _work_path: &PathBuf,
+                 _data_path: &PathBuf,
+                 _recycle_path: &PathBuf,
+                 _user: &String,
+                 _uid: &String,
+                 _flags: &Flag,
+                 _rm_stack: &mut Vec<RMRecord>,
+                 _args: &Vec<String>,
+    ) -> Result<bool, HinaError> {
+        // --diff takes both dumps at once
+        debug_fn!(_work_path,_data_path,_recycle_path,_user,_uid,_flags,_rm_stack,_args);
+        if _flags.parse_bool(vec!["help"]) || !_flags.parse_bool(vec!["diff"]) {
+            return Ok(false);
+        }
+        if _args.len() != 2 {
+            return Err(ConfigParseError(String::from("--diff expects two directories written by --dump")));
+        }
+        let before = get_execute_target(_work_path, &PathBuf::from(&_args[0]))?;
+        let after = get_execute_target(_work_path, &PathBuf::from(&_args[1]))?;
+        let spec_pattern = _flags.parse_string(vec!["i", "input"]);
+        let sort_by = _flags.parse_string(vec!["s", "sort-by"]);
+        let human_readable = _flags.parse_bool(vec!["h", "human-readable"]);
+        Process::diff_dump(&before, &after, &spec_pattern, &sort_by, human_readable)?;
+        Ok(true)
+    }
 }
 
 impl Process {
-    fn print_help() -> Result<(), HinaError> {
+    fn print_help(flags: &Flag) -> Result<(), HinaError> {
+        debug_fn!(flags);
+        func::print_help("ps", "[OPTION]... [PATH]", flags)
+    }
+
+    pub fn get_all_process() -> Result<Vec<ProcessInfo>, HinaError> {
         debug_fn!();
-        execute_command_in_terminal("man", vec!["hina-ps"])?;
-        Ok(())
+        let proc_dir = match fs::read_dir("/proc") {
+            Ok(proc_dir) => { proc_dir }
+            Err(err) => {
+                debugln!("Unable to read /proc: {}, fall back to ps", err);
+                return Process::get_all_process_from_ps();
+            }
+        };
+        let boot_time = fs::read_to_string("/proc/stat")
+            .unwrap_or_default()
+            .lines()
+            .find_map(|line| line.strip_prefix("btime "))
+            .and_then(|btime| btime.trim().parse().ok());
+        let uptime = fs::read_to_string("/proc/uptime")
+            .unwrap_or_default()
+            .split_whitespace()
+            .next()
+            .and_then(|uptime| uptime.parse().ok());
+        let (boot_time, uptime) = match (boot_time, uptime) {
+            (Some(boot_time), Some(uptime)) => { (boot_time, uptime) }
+            _ => {
+                debugln!("Unable to read boot time from /proc, fall back to ps");
+                return Process::get_all_process_from_ps();
+            }
+        };
+        let users = read_users();
+        let mut pids: Vec<usize> = proc_dir
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+            .collect();
+        pids.sort();
+        let mut all_process = Vec::new();
+        for pid in pids {
+            // Processes may exit while being read
+            match ProcessInfo::read(pid, &users, boot_time, uptime) {
+                Ok(proc_info) => { all_process.push(proc_info); }
+                Err(err) => { debugln!("Skip process {}: {:?}", pid, err); }
+            }
+        }
+        Ok(all_process)
     }
 
-    fn get_all_process() -> Result<Vec<ProcessInfo>, HinaError> {
+    fn get_all_process_from_ps() -> Result<Vec<ProcessInfo>, HinaError> {
         debug_fn!();
         let command = format!("ps -ef | sed -n '2,$p'");
         let output = execute_command(&command)?;
-        let entries: Vec<&str> = output.trim().split("\n").collect();
         let mut all_process = Vec::new();
-        for entry in entries {
-            all_process.push(ProcessInfo::from(entry));
+        for entry in output.lines().filter(|line| !line.trim().is_empty()) {
+            match ProcessInfo::from(entry) {
+                Ok(proc_info) => { all_process.push(proc_info); }
+                Err(err) => { debugln!("Skip process: {:?}", err); }
+            }
         }
         Ok(all_process)
     }
 
-    fn get_ps_head() -> Result<String, HinaError> {
+    fn print_process(process_list: &Vec<&ProcessInfo>) {
+        debug_fn!();
+        let head: Vec<String> = ["UID", "PID", "PPID", "C", "S", "THR", "STIME", "TTY", "TIME", "CMD"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        let data: Vec<Vec<String>> = process_list.iter().map(|x| x.to_row()).collect();
+        print_info(&head, &data, head.len());
+    }
+
+    pub fn list_pids() -> Result<Vec<(usize, String)>, HinaError> {
+        // Pid and command of every process, offered when completing --track
         debug_fn!();
-        let command = String::from("ps -ef | sed -n '1p'");
-        Ok(execute_command(&command)?)
+        Ok(Process::get_all_process()?
+            .into_iter()
+            .map(|proc_info| (proc_info.get_pid(), proc_info.get_cmd().clone()))
+            .collect())
     }
 
     pub fn build_proc_map_list(smap_input: &String, cmd_input: Option<&String>) -> Result<ProcessMap, HinaError> {
@@ -239,8 +811,9 @@ impl Process {
                 smap_block.push(line);
             }
         }
+        map_list.push(ProcessMapMeta::from(&smap_block, cmd_input.unwrap_or(&"".to_string()))?);
 
-        Ok(ProcessMap::from(map_list))
+        ProcessMap::from(map_list)
     }
 
     pub fn read_mem_detail_from_proc(proc_id: usize) -> Result<Option<ProcessMap>, HinaError> {
@@ -253,7 +826,11 @@ impl Process {
                 return Ok(None);
             }
             let mut smap_contents = String::new();
-            smap.unwrap().read_to_string(&mut smap_contents).unwrap();
+            // The process may exit while being read, and kernel threads have no memory maps
+            let read = smap.unwrap().read_to_string(&mut smap_contents);
+            if read.is_err() || smap_contents.trim().is_empty() {
+                return Ok(None);
+            }
             let process_map = Process::build_proc_map_list(&smap_contents, None)?;
             Ok(Some(process_map))
         } else {
@@ -265,12 +842,9 @@ impl Process {
         let all_process = Process::get_all_process()?;
         let user_process: Vec<&ProcessInfo> = all_process
             .iter()
-            .filter(|x| &x._uid == user || &x._uid == uid)
+            .filter(|x| x.belongs_to(user, uid))
             .collect();
-        println!("{}", Process::get_ps_head()?);
-        for process in user_process {
-            println!("{}", process._origin);
-        }
+        Process::print_process(&user_process);
         Ok(())
     }
 
@@ -281,12 +855,9 @@ impl Process {
         let all_process = Process::get_all_process()?;
         let user_process: Vec<&ProcessInfo> = all_process
             .iter()
-            .filter(|x| (&x._uid == user || &x._uid == uid) && x._cmd.contains(process_name))
+            .filter(|x| x.belongs_to(user, uid) && x._cmd.contains(process_name))
             .collect();
-        println!("{}", Process::get_ps_head()?);
-        for process in user_process {
-            println!("{}", process._origin);
-        }
+        Process::print_process(&user_process);
         Ok(())
     }
 
@@ -294,25 +865,21 @@ impl Process {
         debug_fn!(process_id);
         let all_process = Process::get_all_process()?;
         let mut process_route = HashMap::new();
-        for process in all_process {
-            process_route.insert(process._pid, (process._ppid, process._origin));
+        for process in &all_process {
+            process_route.insert(process._pid, process);
         }
         if process_route.contains_key(&process_id) {
             let mut pid = process_id;
-            let mut ppid;
-            let mut ancestors = vec![&process_route[&pid].1];
-            loop {
-                ppid = process_route[&pid].0;
-                if ppid == 0 {
+            let mut ancestors = vec![process_route[&pid]];
+            // The parent may have exited or live outside this pid namespace
+            while let Some(parent) = process_route.get(&process_route[&pid]._ppid) {
+                if parent._pid == pid {
                     break;
                 }
-                pid = ppid;
-                ancestors.push(&process_route[&pid].1);
-            }
-            println!("{}", Process::get_ps_head()?);
-            for ancestor in ancestors {
-                println!("{}", ancestor);
+                pid = parent._pid;
+                ancestors.push(parent);
             }
+            Process::print_process(&ancestors);
         }
         Ok(())
     }
@@ -333,7 +900,7 @@ impl Process {
         let all_process = Process::get_all_process()?;
         let user_process: Vec<&ProcessInfo> = all_process
             .iter()
-            .filter(|x| &x._uid == user || &x._uid == uid)
+            .filter(|x| x.belongs_to(user, uid))
             .collect();
         debug_var!(user_process);
         for process in user_process {
@@ -344,6 +911,8 @@ impl Process {
             execute_command(&command)?;
             let command = String::from(format!("cat /proc/{}/cmdline > {}/cmdline", pid, &target.display()));
             execute_command(&command)?;
+            let command = format!("cat /proc/{}/status > {}/status", pid, &target.display());
+            execute_command(&command)?;
             target.pop();
         }
         Ok(())
@@ -357,8 +926,25 @@ impl Process {
         let all_process = Process::get_all_process()?;
         let user_process: Vec<&ProcessInfo> = all_process
             .iter()
-            .filter(|x| &x._uid == user || &x._uid == uid)
+            .filter(|x| x.belongs_to(user, uid))
             .collect();
+        let mut details = Vec::new();
+        for proc_info in user_process {
+            if let Some(proc_map) = Process::read_mem_detail_from_proc(proc_info._pid)? {
+                details.push(ProcessMemDetail {
+                    _user: proc_info._user.clone(),
+                    _pid: proc_info._pid,
+                    _cmd: proc_info._cmd.clone(),
+                    _map: proc_map,
+                });
+            }
+        }
+        Process::print_mem_detail(&details, sort_by, human_readable);
+        Ok(())
+    }
+
+    fn print_mem_detail(details: &Vec<ProcessMemDetail>, sort_by: &String, human_readable: bool) {
+        debug_fn!(sort_by,human_readable);
         let mut output_list = Vec::new();
         let head = vec!["UID".to_string(),
                         "PID".to_string(),
@@ -378,38 +964,35 @@ impl Process {
             sort_by_map[sort_by.as_str()] as usize
         } else { 1 };
 
-        for proc_info in user_process {
-            let proc_map_opt = Process::read_mem_detail_from_proc(proc_info._pid)?;
-            if proc_map_opt.is_some() {
-                let proc_map = proc_map_opt.unwrap();
-                let output_info: Vec<String>;
-                if human_readable {
-                    output_info = vec![proc_info._uid.to_string(),
-                                       proc_info._pid.to_string(),
-                                       proc_map.get_total_as_human_readable("size"),
-                                       proc_map.get_total_as_human_readable("swap"),
-                                       proc_map.get_total_as_human_readable("pss"),
-                                       proc_map.get_total_as_human_readable("rss"),
-                                       proc_info._cmd.to_string(),
-                                       proc_map.get_total_as_str("size"),
-                                       proc_map.get_total_as_str("swap"),
-                                       proc_map.get_total_as_str("pss"),
-                                       proc_map.get_total_as_str("rss"), ];
-                } else {
-                    output_info = vec![proc_info._uid.to_string(),
-                                       proc_info._pid.to_string(),
-                                       proc_map.get_total_as_kb("size"),
-                                       proc_map.get_total_as_kb("swap"),
-                                       proc_map.get_total_as_kb("pss"),
-                                       proc_map.get_total_as_kb("rss"),
-                                       proc_info._cmd.to_string(),
-                                       proc_map.get_total_as_str("size"),
-                                       proc_map.get_total_as_str("swap"),
-                                       proc_map.get_total_as_str("pss"),
-                                       proc_map.get_total_as_str("rss"), ];
-                }
-                output_list.push(output_info);
+        for detail in details {
+            let proc_map = &detail._map;
+            let output_info: Vec<String>;
+            if human_readable {
+                output_info = vec![detail._user.to_string(),
+                                   detail._pid.to_string(),
+                                   proc_map.get_total_as_human_readable("size"),
+                                   proc_map.get_total_as_human_readable("swap"),
+                                   proc_map.get_total_as_human_readable("pss"),
+                                   proc_map.get_total_as_human_readable("rss"),
+                                   detail._cmd.to_string(),
+                                   proc_map.get_total_as_str("size"),
+                                   proc_map.get_total_as_str("swap"),
+                                   proc_map.get_total_as_str("pss"),
+                                   proc_map.get_total_as_str("rss"), ];
+            } else {
+                output_info = vec![detail._user.to_string(),
+                                   detail._pid.to_string(),
+                                   proc_map.get_total_as_kb("size"),
+                                   proc_map.get_total_as_kb("swap"),
+                                   proc_map.get_total_as_kb("pss"),
+                                   proc_map.get_total_as_kb("rss"),
+                                   detail._cmd.to_string(),
+                                   proc_map.get_total_as_str("size"),
+                                   proc_map.get_total_as_str("swap"),
+                                   proc_map.get_total_as_str("pss"),
+                                   proc_map.get_total_as_str("rss"), ];
             }
+            output_list.push(output_info);
         }
         output_list.sort_by(|x1, x2| {
             let x3: u64 = x1[sort_by_ind].parse().unwrap();
@@ -417,6 +1000,572 @@ impl Process {
             return x3.partial_cmp(&x4).unwrap();
         });
         print_info(&head, &output_list, 7);
+    }
+
+    fn get_mem_usage(user: &String, uid: &String, pattern: &String) -> Result<Vec<ProcessMemUsage>, HinaError> {
+        debug_fn!(user,uid,pattern);
+        let mut usages = Vec::new();
+        for proc_info in Process::get_all_process()? {
+            // Leave out hina itself, so it can not be signaled by mistake
+            if proc_info._pid == process::id() as usize
+                || !proc_info.belongs_to(user, uid)
+                || !proc_info._cmd.contains(pattern.as_str()) {
+                continue;
+            }
+            if let Some(proc_map) = Process::read_mem_detail_from_proc(proc_info._pid)? {
+                let mem = ["size", "swap", "pss", "rss"].map(|key| proc_map.get_total(key));
+                usages.push(ProcessMemUsage::from(&proc_info._user, proc_info._pid, &proc_info._cmd, mem));
+            }
+        }
+        Ok(usages)
+    }
+
+    pub fn watch_proc_mem(user: &String,
+                          uid: &String,
+                          pattern: &String,
+                          sort_by: &String,
+                          human_readable: bool,
+                          interval: &String) -> Result<(), HinaError> {
+        // Redraw the --xray table every interval until q is pressed
+        debug_fn!(user,uid,pattern,sort_by,human_readable,interval);
+        let (interval_str, duration) = parse_interval(interval)?;
+        let mut term = RawTerminal::open()?;
+        let mut sort_ind = WATCH_SORT_KEYS.iter().position(|key| key == sort_by).unwrap_or(0);
+        let mut human_readable = human_readable;
+        let mut selected: Option<usize> = None;
+        let mut pending: Option<(usize, &str)> = None;
+        let mut status = String::new();
+        let mut last: HashMap<usize, [u64; 4]> = HashMap::new();
+        loop {
+            let mut usages = Process::get_mem_usage(user, uid, pattern)?;
+            last = Process::track_mem_delta(&mut usages, &last);
+            let deadline = Instant::now() + duration;
+            loop {
+                Process::sort_mem_usage(&mut usages, WATCH_SORT_KEYS[sort_ind]);
+                // Follow the selected process by pid, it may have exited
+                let mut ind = selected
+                    .and_then(|pid| usages.iter().position(|usage| usage._pid == pid))
+                    .unwrap_or(0);
+                let head = format!("hina ps --watch  every {}  sort by {}  {} processes  {}",
+                                   interval_str, WATCH_SORT_KEYS[sort_ind], usages.len(), Local::now().format("%H:%M:%S"));
+                let hint = if status.is_empty() {
+                    String::from("j/k select  s sort  h units  t SIGTERM  K SIGKILL  q quit")
+                } else { status.clone() };
+                let (rows, cols) = term.get_size();
+                let mut lines = vec![head, hint];
+                lines.extend(Process::render_mem_usage(&usages, ind, human_readable, rows.saturating_sub(2), cols));
+                term.draw(&lines);
+
+                let mut key = None;
+                while key.is_none() && Instant::now() < deadline {
+                    key = term.read_key();
+                }
+                let key = match key {
+                    Some(key) => { key }
+                    None => { break; }
+                };
+                status.clear();
+                if let Some((pid, signal)) = pending.take() {
+                    status = if key == Key::Char('y') {
+                        Process::send_signal(pid, signal)
+                    } else {
+                        String::from("Cancelled")
+                    };
+                    continue;
+                }
+                match key {
+                    Key::Char('q') | Key::Char('\x03') => { return Ok(()); }
+                    Key::Up | Key::Char('k') => { ind = ind.saturating_sub(1); }
+                    Key::Down | Key::Char('j') => { ind = min(ind + 1, usages.len().saturating_sub(1)); }
+                    Key::Char('s') => { sort_ind = (sort_ind + 1) % WATCH_SORT_KEYS.len(); }
+                    Key::Char('h') => { human_readable = !human_readable; }
+                    Key::Char('t') | Key::Char('K') => {
+                        if let Some(usage) = usages.get(ind) {
+                            let signal = if key == Key::Char('t') { "TERM" } else { "KILL" };
+                            status = format!("Send SIG{} to {} {}? [y/N]", signal, usage._pid, usage._cmd);
+                            pending = Some((usage._pid, signal));
+                        }
+                    }
+                    _ => {}
+                }
+                selected = usages.get(ind).map(|usage| usage._pid);
+            }
+        }
+    }
+
+    pub fn track_mem_delta(usages: &mut [ProcessMemUsage], last: &HashMap<usize, [u64; 4]>) -> HashMap<usize, [u64; 4]> {
+        // Set the change since the last refresh, returns the usage to compare the next refresh with
+        debug_fn!(last);
+        for usage in usages.iter_mut() {
+            usage._delta = last
+                .get(&usage._pid)
+                .map(|mem| [0, 1, 2, 3].map(|i| usage._mem[i] as i64 - mem[i] as i64));
+        }
+        usages.iter().map(|usage| (usage._pid, usage._mem)).collect()
+    }
+
+    pub fn sort_mem_usage(usages: &mut [ProcessMemUsage], sort_by: &str) {
+        debug_fn!(sort_by);
+        let mem_ind = match sort_by {
+            "size" => { 0 }
+            "swap" => { 1 }
+            "pss" => { 2 }
+            "rss" => { 3 }
+            _ => {
+                usages.sort_by_key(|usage| usage._pid);
+                return;
+            }
+        };
+        usages.sort_by(|x1, x2| x2._mem[mem_ind].cmp(&x1._mem[mem_ind]).then(x1._pid.cmp(&x2._pid)));
+    }
+
+    pub fn render_mem_usage(usages: &[ProcessMemUsage],
+                            selected: usize,
+                            human_readable: bool,
+                            height: usize,
+                            width: usize) -> Vec<String> {
+        // Table lines fitting height and width, growing processes in red and the selected one reversed
+        debug_fn!(selected,human_readable,height,width);
+        let head: Vec<String> = ["UID", "PID", "SIZE", "SWAP", "PSS", "RSS", "+SWAP", "+PSS", "+RSS"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        let data: Vec<Vec<String>> = usages
+            .iter()
+            .map(|usage| {
+                let mut cells = vec![usage._user.clone(), usage._pid.to_string()];
+                cells.extend(usage._mem.iter().map(|kb| format_kb(*kb, human_readable)));
+                cells.extend([1, 2, 3].map(|i| match usage._delta {
+                    Some(delta) => { format_kb_delta(delta[i], human_readable) }
+                    None => { String::from("-") }
+                }));
+                cells
+            })
+            .collect();
+        let mut max_len: Vec<usize> = head.iter().map(|x| x.len()).collect();
+        for cells in &data {
+            for (i, cell) in cells.iter().enumerate() {
+                max_len[i] = max(max_len[i], cell.len());
+            }
+        }
+        let render = |cells: &Vec<String>, cmd: &str| -> String {
+            let mut line = String::new();
+            for (i, cell) in cells.iter().enumerate() {
+                line += &func::gen_str_width_ctrl(cell, max_len[i] + 2);
+            }
+            line += cmd;
+            line.chars().take(width).collect()
+        };
+        let mut lines = vec![render(&head, "CMD")];
+        // Scroll so the selected process stays visible
+        let visible = height.saturating_sub(1);
+        let offset = (selected + 1).saturating_sub(visible);
+        for (i, usage) in usages.iter().enumerate().skip(offset).take(visible) {
+            let line = render(&data[i], &usage._cmd);
+            let growing = usage._delta.is_some_and(|delta| delta[2] > 0 || delta[3] > 0);
+            let line = if growing { line.red().to_string() } else { line };
+            lines.push(if i == selected { line.reversed().to_string() } else { line });
+        }
+        lines
+    }
+
+    fn send_signal(pid: usize, signal: &str) -> String {
+        // The result of kill for the status line
+        debug_fn!(pid,signal);
+        let command = format!("kill -s {} {} 2>&1", signal, pid);
+        match execute_command(&command) {
+            Ok(output) if output.trim().is_empty() => { format!("Sent SIG{} to {}", signal, pid) }
+            Ok(output) => { output.trim().to_string() }
+            Err(err) => { format!("Unable to send SIG{} to {}: {:?}", signal, pid, err) }
+        }
+    }
+
+    pub fn record_proc_mem(user: &String,
+                           uid: &String,
+                           target: &String,
+                           interval: &String,
+                           out: &PathBuf,
+                           count: usize,
+                           with_maps: bool) -> Result<(), HinaError> {
+        // Append a sample of each target process to out every interval, a pid target
+        // is recorded whoever owns it, a pattern only matches processes of the user
+        debug_fn!(user,uid,target,interval,out,count,with_maps);
+        let (interval_str, duration) = parse_interval(interval)?;
+        let target_pid: Option<usize> = target.parse().ok();
+        let mut file = match OpenOptions::new().create(true).append(true).open(out) {
+            Ok(file) => { file }
+            Err(err) => { return Err(FileOpenError(format!("Unable to open {}: {}", out.display(), err))); }
+        };
+        println!("Recording {} every {} into {}", target, interval_str, out.display());
+        let mut n = 0;
+        loop {
+            let targets: Vec<ProcessInfo> = Process::get_all_process()?
+                .into_iter()
+                .filter(|x| match target_pid {
+                    Some(pid) => { x._pid == pid }
+                    None => {
+                        x._pid != process::id() as usize && x.belongs_to(user, uid) && x._cmd.contains(target.as_str())
+                    }
+                })
+                .collect();
+            let now = Local::now();
+            let time = now.format(TIME_FORMAT).to_string();
+            let mut lines = String::new();
+            for proc_info in &targets {
+                if let Some(proc_map) = Process::read_mem_detail_from_proc(proc_info._pid)? {
+                    let sample = MemSample::from(&time, proc_info._pid, &proc_info._cmd, &proc_map, with_maps);
+                    match serde_json::to_string(&sample) {
+                        Ok(line) => { lines += &(line + "\n"); }
+                        Err(err) => { return Err(FileWriteError(format!("Unable to encode sample of {}: {}", proc_info._pid, err))); }
+                    }
+                }
+            }
+            if lines.is_empty() {
+                if n == 0 {
+                    return Err(ProcessNotFoundError(format!("No readable process matches \'{}\'", target)));
+                }
+                println!("No process left to record");
+                break;
+            }
+            match file.write_all(lines.as_bytes()) {
+                Ok(_) => {}
+                Err(err) => { return Err(FileWriteError(format!("Unable to write {}: {}", out.display(), err))); }
+            }
+            n += 1;
+            println!("{}  sample {}  {} processes", now.format(SECOND_FORMAT), n, lines.lines().count());
+            if count > 0 && n >= count {
+                break;
+            }
+            thread::sleep(duration);
+        }
+        Ok(())
+    }
+
+    pub fn analyze_proc_mem(input: &PathBuf, human_readable: bool) -> Result<(), HinaError> {
+        // Report the growth of each process in a --record file, see LEAK_MIN_GROWTH for the verdict
+        debug_fn!(input,human_readable);
+        for ((pid, _), samples) in Process::load_mem_samples(input)? {
+            Process::print_mem_growth(pid, &samples, human_readable);
+        }
+        Ok(())
+    }
+
+    pub fn load_mem_samples(input: &PathBuf) -> Result<IndexMap<(usize, String), Vec<MemSample>>, HinaError> {
+        // Samples of a --record file by process, oldest first, a reused pid running
+        // another command is another process
+        debug_fn!(input);
+        let content = match fs::read_to_string(input) {
+            Ok(content) => { content }
+            Err(err) => { return Err(FileOpenError(format!("Unable to open {}: {}", input.display(), err))); }
+        };
+        let mut records: IndexMap<(usize, String), Vec<MemSample>> = IndexMap::new();
+        for (i, line) in content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            let sample: MemSample = match serde_json::from_str(line) {
+                Ok(sample) => { sample }
+                Err(err) => { return Err(BadFileError(format!("{} line {}: {}", input.display(), i + 1, err))); }
+            };
+            if func::parse_time(&sample.time).is_none() {
+                return Err(BadFileError(format!("{} line {}: bad time \'{}\'", input.display(), i + 1, sample.time)));
+            }
+            records.entry((sample.pid, sample.cmd.clone())).or_default().push(sample);
+        }
+        if records.is_empty() {
+            return Err(BadFileError(format!("{} has no samples", input.display())));
+        }
+        for samples in records.values_mut() {
+            samples.sort_by_key(|sample| func::parse_time(&sample.time));
+        }
+        Ok(records)
+    }
+
+    fn print_mem_growth(pid: usize, samples: &[MemSample], human_readable: bool) {
+        debug_fn!(pid,human_readable);
+        let (first, last) = (&samples[0], &samples[samples.len() - 1]);
+        let start = func::parse_time(&first.time).unwrap();
+        let end = func::parse_time(&last.time).unwrap();
+        println!("{} {}  {}", "PID".bold(), pid.to_string().bold(), last.cmd);
+        println!("  {} samples over {}, from {} to {}",
+                 samples.len(),
+                 func::format_age(end - start),
+                 start.format(SECOND_FORMAT),
+                 end.format(SECOND_FORMAT));
+
+        let mut verdict_fit = (0.0, 0.0);
+        for key in ["rss", "pss", "swap", "pss+swap"] {
+            let points: Vec<(f64, f64)> = samples
+                .iter()
+                .map(|sample| {
+                    let time = func::parse_time(&sample.time).unwrap() - start;
+                    (time.num_milliseconds() as f64 / 60000.0, sample.get_total(key) as f64)
+                })
+                .collect();
+            let (slope, r2) = linear_fit(&points);
+            if key == "pss+swap" {
+                verdict_fit = (slope, r2);
+            }
+            let (from, to) = (first.get_total(key), last.get_total(key));
+            let percent = if from > 0 { (to as f64 - from as f64) * 100.0 / from as f64 } else { 0.0 };
+            println!("  {:<9}{} -> {}  ({}, {:+.1}%)  slope {}/min  r2 {:.2}",
+                     key.to_uppercase(),
+                     format_kb(from, human_readable),
+                     format_kb(to, human_readable),
+                     format_kb_delta(to as i64 - from as i64, human_readable),
+                     percent,
+                     format_kb_delta(slope.round() as i64, human_readable),
+                     r2);
+        }
+
+        let (from, to) = (first.get_total("pss+swap"), last.get_total("pss+swap"));
+        let growth = if from > 0 { (to as f64 - from as f64) / from as f64 } else { 0.0 };
+        let (slope, r2) = verdict_fit;
+        let verdict = match judge_leak(samples.len(), growth, slope, r2) {
+            LeakVerdict::TooFewSamples => { "not enough samples".normal() }
+            LeakVerdict::Likely => { "likely leak, growing steadily".red().bold() }
+            LeakVerdict::Possible => { "possible leak, growing unsteadily".yellow() }
+            LeakVerdict::NoLeak => { "no leak".green() }
+        };
+        println!("  Verdict: {}", verdict);
+
+        // Mappings are compared between the first and the last sample recorded with --mappings
+        let mapped: Vec<&MemSample> = samples.iter().filter(|sample| !sample.maps.is_empty()).collect();
+        if mapped.len() >= 2 {
+            let (before, after) = (mapped[0], mapped[mapped.len() - 1]);
+            let before_pss = before.get_map_totals("pss");
+            let before_swap = before.get_map_totals("swap");
+            let after_swap = after.get_map_totals("swap");
+            let mut grown: Vec<(i64, String)> = after
+                .get_map_totals("pss")
+                .into_iter()
+                .map(|(name, pss)| {
+                    let old = before_pss.get(&name).copied().unwrap_or(0) + before_swap.get(&name).copied().unwrap_or(0);
+                    let new = pss + after_swap.get(&name).copied().unwrap_or(0);
+                    (new as i64 - old as i64, name)
+                })
+                .filter(|(delta, _)| *delta > 0)
+                .collect();
+            grown.sort_by(|x1, x2| x2.0.cmp(&x1.0).then(x1.1.cmp(&x2.1)));
+            if grown.is_empty() {
+                println!("  No mapping grew");
+            } else {
+                println!("  Grown mappings, PSS+SWAP:");
+                for (delta, name) in grown.iter().take(10) {
+                    println!("    {:>14}  {}", format_kb_delta(*delta, human_readable), name);
+                }
+            }
+        }
+        println!();
+    }
+
+    pub fn show_proc_maps(pid: usize, sort_by: &String, human_readable: bool) -> Result<(), HinaError> {
+        // Memory of one process by backing file, then by type of mapping
+        debug_fn!(pid,sort_by,human_readable);
+        let proc_map = match Process::read_mem_detail_from_proc(pid)? {
+            Some(proc_map) => { proc_map }
+            None => { return Err(ProcessNotFoundError(format!("Unable to read the memory maps of process {}", pid))); }
+        };
+        let cmd = read_proc_file(pid, "cmdline").unwrap_or_default().replace('\0', " ").trim().to_string();
+        let groups = group_maps(&proc_map);
+        let mut kinds: IndexMap<&str, MapGroup> = IndexMap::new();
+        let mut total = MapGroup { kind: "total", name: String::new(), count: 0, kb: [0; 5] };
+        for group in groups.values() {
+            kinds.entry(group.kind).or_insert(MapGroup { kind: group.kind, name: String::new(), count: 0, kb: [0; 5] }).merge(group);
+            total.merge(group);
+        }
+        let mut groups: Vec<MapGroup> = groups.into_values().collect();
+        let mut kinds: Vec<MapGroup> = kinds.into_values().collect();
+        MapGroup::sort(&mut groups, sort_by);
+        MapGroup::sort(&mut kinds, sort_by);
+        kinds.push(total);
+
+        println!("{} {}  {}", "PID".bold(), pid.to_string().bold(), cmd);
+        let head: Vec<String> = ["TYPE", "COUNT", "SIZE", "RSS", "PSS", "SWAP", "DIRTY", "NAME"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        let rows: Vec<Vec<String>> = groups.iter().map(|group| group.to_row(human_readable)).collect();
+        print_info(&head, &rows, head.len());
+        println!();
+        let rows: Vec<Vec<String>> = kinds.iter().map(|group| group.to_row(human_readable)).collect();
+        print_info(&head, &rows, head.len() - 1);
+        Ok(())
+    }
+
+    pub fn load_dump(dump: &PathBuf) -> Result<Vec<ProcessMemDetail>, HinaError> {
+        // Read a --dump directory, <pid>/smaps with the cmdline and status next to it
+        debug_fn!(dump);
+        let entries = match fs::read_dir(dump) {
+            Ok(entries) => { entries }
+            Err(err) => { return Err(DirReadError(format!("Unable to read {}: {}", dump.display(), err))); }
+        };
+        let mut pids: Vec<usize> = entries
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+            .collect();
+        pids.sort();
+        let mut details = Vec::new();
+        for pid in pids {
+            let dir = dump.join(pid.to_string());
+            let read = |name: &str| fs::read(dir.join(name)).map(|content| String::from_utf8_lossy(&content).to_string());
+            // Kernel threads and processes that exited while dumping leave an empty smaps
+            let smaps = match read("smaps") {
+                Ok(smaps) if !smaps.trim().is_empty() => { smaps }
+                _ => {
+                    debugln!("Skip {}, no memory maps", dir.display());
+                    continue;
+                }
+            };
+            let cmdline = read("cmdline").unwrap_or_default();
+            // The uid is shown as a number, as user names may differ between hosts
+            let uid = read("status")
+                .unwrap_or_default()
+                .lines()
+                .find_map(|line| line.strip_prefix("Uid:"))
+                .and_then(|ids| ids.split_whitespace().nth(1))
+                .unwrap_or("-")
+                .to_string();
+            let proc_map = match Process::build_proc_map_list(&smaps, Some(&cmdline)) {
+                Ok(proc_map) => { proc_map }
+                Err(BadFileError(err)) => { return Err(BadFileError(format!("{}: {}", dir.join("smaps").display(), err))); }
+                Err(err) => { return Err(err); }
+            };
+            let cmd = proc_map._data[0]._cmd.clone();
+            details.push(ProcessMemDetail { _user: uid, _pid: pid, _cmd: cmd, _map: proc_map });
+        }
+        if details.is_empty() {
+            return Err(BadFileError(format!("{} has no process dumped by ps --dump", dump.display())));
+        }
+        Ok(details)
+    }
+
+    pub fn show_dump(dump: &PathBuf, pattern: &String, sort_by: &String, human_readable: bool) -> Result<(), HinaError> {
+        debug_fn!(dump,pattern,sort_by,human_readable);
+        let mut details = Process::load_dump(dump)?;
+        details.retain(|detail| detail._cmd.contains(pattern.as_str()));
+        Process::print_mem_detail(&details, sort_by, human_readable);
+        Ok(())
+    }
+
+    pub fn diff_dump(before: &PathBuf,
+                     after: &PathBuf,
+                     pattern: &String,
+                     sort_by: &String,
+                     human_readable: bool) -> Result<(), HinaError> {
+        // Compare two dumps by process, a pid running another command is taken as a new process
+        debug_fn!(before,after,pattern,sort_by,human_readable);
+        let mut before_details = Process::load_dump(before)?;
+        let mut after_details = Process::load_dump(after)?;
+        before_details.retain(|detail| detail._cmd.contains(pattern.as_str()));
+        after_details.retain(|detail| detail._cmd.contains(pattern.as_str()));
+        let before_map: HashMap<usize, &ProcessMemDetail> = before_details.iter().map(|x| (x._pid, x)).collect();
+        let after_map: HashMap<usize, &ProcessMemDetail> = after_details.iter().map(|x| (x._pid, x)).collect();
+
+        let mut pairs: Vec<(Option<&ProcessMemDetail>, Option<&ProcessMemDetail>)> = Vec::new();
+        for detail in &before_details {
+            match after_map.get(&detail._pid) {
+                Some(other) if other._cmd == detail._cmd => { pairs.push((Some(detail), Some(other))); }
+                _ => { pairs.push((Some(detail), None)); }
+            }
+        }
+        for detail in &after_details {
+            match before_map.get(&detail._pid) {
+                Some(other) if other._cmd == detail._cmd => {}
+                _ => { pairs.push((None, Some(detail))); }
+            }
+        }
+
+        let keys = ["size", "swap", "pss", "rss"];
+        let get_delta = |pair: &(Option<&ProcessMemDetail>, Option<&ProcessMemDetail>)| -> [i64; 4] {
+            keys.map(|key| {
+                let value = |detail: Option<&ProcessMemDetail>| detail.map_or(0, |x| x._map.get_total(key) as i64);
+                value(pair.1) - value(pair.0)
+            })
+        };
+        pairs.retain(|pair| get_delta(pair) != [0; 4]);
+        let sort_ind = keys.iter().position(|key| key == sort_by).unwrap_or(2);
+        if sort_by == "pid" {
+            pairs.sort_by_key(|pair| pair.0.or(pair.1).unwrap()._pid);
+        } else {
+            pairs.sort_by_key(|pair| std::cmp::Reverse(get_delta(pair)[sort_ind].abs()));
+        }
+
+        println!("{} processes in {}, {} in {}, {} changed",
+                 before_details.len(), before.display(), after_details.len(), after.display(), pairs.len());
+        if pairs.is_empty() {
+            return Ok(());
+        }
+        let head: Vec<String> = ["STATE", "PID", "SIZE", "+SIZE", "SWAP", "+SWAP", "PSS", "+PSS", "RSS", "+RSS", "CMD"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        let mut total = [0i64; 4];
+        let mut rows = Vec::new();
+        for pair in &pairs {
+            let delta = get_delta(pair);
+            let (state, detail) = match pair {
+                (Some(_), Some(detail)) => { ("changed", detail) }
+                (None, Some(detail)) => { ("new", detail) }
+                (Some(detail), _) => { ("gone", detail) }
+                (None, None) => { continue; }
+            };
+            let mut row = vec![state.to_string(), detail._pid.to_string()];
+            for i in 0..keys.len() {
+                let value = if state == "gone" { 0 } else { detail._map.get_total(keys[i]) };
+                row.push(format_kb(value, human_readable));
+                row.push(format_kb_delta(delta[i], human_readable));
+                total[i] += delta[i];
+            }
+            row.push(detail._cmd.clone());
+            rows.push(row);
+        }
+        let mut total_row = vec![String::from("total"), String::new()];
+        for delta in total {
+            total_row.push(String::new());
+            total_row.push(format_kb_delta(delta, human_readable));
+        }
+        total_row.push(String::new());
+        rows.push(total_row);
+        print_info(&head, &rows, head.len());
+
+        // Mappings of the processes found in both dumps, grouped by backing file like --maps
+        for pair in &pairs {
+            let (before_detail, after_detail) = match pair {
+                (Some(before_detail), Some(after_detail)) => { (before_detail, after_detail) }
+                _ => { continue; }
+            };
+            let before_groups = group_maps(&before_detail._map);
+            let after_groups = group_maps(&after_detail._map);
+            let mut changes: Vec<(&'static str, String, [i64; 3])> = Vec::new();
+            for (key, group) in after_groups.iter().chain(before_groups.iter()) {
+                if changes.iter().any(|(kind, name, _)| (*kind, name) == (key.0, &key.1)) {
+                    continue;
+                }
+                let value = |groups: &IndexMap<(&str, String), MapGroup>, i: usize| groups.get(key).map_or(0, |x| x.kb[i] as i64);
+                let delta = [2, 1, 3].map(|i| value(&after_groups, i) - value(&before_groups, i));
+                if delta != [0; 3] {
+                    changes.push((group.kind, group.name.clone(), delta));
+                }
+            }
+            if changes.is_empty() {
+                continue;
+            }
+            changes.sort_by(|x1, x2| x2.2[0].abs().cmp(&x1.2[0].abs()).then(x1.1.cmp(&x2.1)));
+            println!();
+            println!("{} {}  {}", "PID".bold(), after_detail._pid.to_string().bold(), after_detail._cmd);
+            let head: Vec<String> = ["TYPE", "+PSS", "+RSS", "+SWAP", "NAME"].iter().map(|x| x.to_string()).collect();
+            let rows: Vec<Vec<String>> = changes
+                .iter()
+                .take(DIFF_MAX_MAPPINGS)
+                .map(|(kind, name, delta)| {
+                    let mut row = vec![kind.to_string()];
+                    row.extend(delta.iter().map(|x| format_kb_delta(*x, human_readable)));
+                    row.push(name.clone());
+                    row
+                })
+                .collect();
+            print_info(&head, &rows, head.len());
+            if changes.len() > DIFF_MAX_MAPPINGS {
+                println!("... {} more mappings changed", changes.len() - DIFF_MAX_MAPPINGS);
+            }
+        }
         Ok(())
     }
-}
\ No newline at end of file
+}
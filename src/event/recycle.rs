@@ -1,16 +1,22 @@
-use std::io::stdin;
-use std::path::PathBuf;
+use std::cmp::{max, min};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::fs::{Metadata, Permissions};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Component, Path, PathBuf};
 
 use chrono::{DateTime, Local};
 use colored::Colorize;
+use serde::Serialize;
 
-use crate::{debug_fn, debug_info};
-use crate::core::config::{Flag, RMRecord};
+use crate::{debug_fn, debug_info, debugln};
+use crate::core::config::{BinStore, Compression, Flag, FlagSpec, FlagType, RMRecord, TrashBackend};
 use crate::core::error::HinaError;
-use crate::core::error::HinaError::{FileExistError, OutOfIndexError};
-use crate::core::func;
-use crate::core::func::{execute_command_in_terminal, get_execute_target, split_and_remove_blank};
-use crate::core::global::{DEBUG, RAND_STR_LEN};
+use crate::core::error::HinaError::{BadFileError, BatchError, ConfigParseError, DirCreateError, DirReadError, FileExistError, FileNotExistError, FileWriteError, OutOfIndexError, PartialMoveError, ProtectedPathError};
+use crate::core::{func, protect, store, xdg};
+use crate::core::protect::{ProtectAction, ProtectRule};
+use crate::core::func::{get_execute_target, print_info, split_and_remove_blank};
+use crate::core::global::{BATCH_ID, BIN_COMPRESS, BIN_COMPRESS_AFTER, BIN_QUOTA, BIN_STORE, DEBUG, DEFAULT_SHRED_PASSES, MOUNT_TRASH, QUARANTINE, RAND_STR_LEN, TIME_FORMAT, TRASH_BACKEND, UNKNOWN_ORIGIN};
 use crate::event::base::HinaModuleRun;
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
@@ -19,7 +25,159 @@ pub struct Remove;
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct RecycleBin;
 
+static REMOVE_FLAGS: &[FlagSpec] = &[
+    FlagSpec {
+        overrides: &["i", "I", "interactive"],
+        ..FlagSpec::new("force", &["f"], FlagType::Bool, "", "Ignore nonexistent files and missing operands, never prompt.")
+    },
+    FlagSpec {
+        overrides: &["force", "I", "interactive"],
+        ..FlagSpec::new("i", &[], FlagType::Bool, "", "Prompt before every removal.")
+    },
+    FlagSpec {
+        overrides: &["force", "i", "interactive"],
+        ..FlagSpec::new("I", &[], FlagType::Bool, "", "Prompt once before removing more than three files or any directory.")
+    },
+    FlagSpec {
+        choices: &["never", "no", "none", "once", "always", "yes"],
+        overrides: &["force", "i", "I"],
+        ..FlagSpec::new("interactive", &[], FlagType::OptStr, "WHEN", "Prompt never, once (-I) or always (-i, the default).")
+    },
+    FlagSpec::new("verbose", &["v"], FlagType::Bool, "", "Explain what is being done."),
+    FlagSpec::new("recursive", &["r", "R"], FlagType::Bool, "", "Accepted for compatibility, directories are always removed as a whole."),
+    FlagSpec::new("dir", &["d"], FlagType::Bool, "", "Accepted for compatibility, directories are always removed as a whole."),
+    FlagSpec::new("one-file-system", &[], FlagType::Bool, "", "Skip a directory that contains mount points of other filesystems."),
+    FlagSpec {
+        choices: &["all"],
+        ..FlagSpec::new("preserve-root", &[], FlagType::OptStr, "all", "Do not remove / or $HOME (default), with all also refuse mount points.")
+    },
+    FlagSpec::new("no-preserve-root", &[], FlagType::Bool, "", "Do not treat / and $HOME specially."),
+    FlagSpec::new("shred", &[], FlagType::Bool, "", "Overwrite and delete regular files instead of moving them to the recycle bin."),
+    FlagSpec { default: "3", ..FlagSpec::new("passes", &[], FlagType::Uint, "N", "With --shred, overwrite N times.") },
+    FlagSpec::new("zero", &[], FlagType::Bool, "", "With --shred, overwrite with zeros instead of random data."),
+    FlagSpec::new("atomic", &[], FlagType::Bool, "", "Remove all targets or none, moving back what was removed when any target fails."),
+];
+
+static RECYCLE_BIN_FLAGS: &[FlagSpec] = &[
+    FlagSpec::new("list", &["ls"], FlagType::Bool, "", "List the contents of the recycle bin."),
+    FlagSpec {
+        default: "time",
+        choices: &["time", "size", "path"],
+        ..FlagSpec::new("sort-by", &[], FlagType::Str, "KEY", "With --list, sort by time, size or path.")
+    },
+    FlagSpec::new("filter", &[], FlagType::Str, "PATTERN", "With --list, only show entries whose original path matches PATTERN."),
+    FlagSpec::new("since", &[], FlagType::Str, "TIME", "With --list, only show entries removed after TIME, a date or an age like 3d."),
+    FlagSpec::new("until", &[], FlagType::Str, "TIME", "With --list, only show entries removed before TIME."),
+    FlagSpec {
+        choices: &["table", "json"],
+        ..FlagSpec::new("format", &[], FlagType::Str, "FORMAT", "With --list, print a table (default) or json.")
+    },
+    FlagSpec {
+        complete: "records",
+        ..FlagSpec::new("restore", &["rs"], FlagType::Bool, "", "Restore the given indices, ranges, paths or patterns, or ask for them.")
+    },
+    FlagSpec::new("last", &[], FlagType::OptUint, "N", "Restore the N most recently removed entries, 1 if N is omitted."),
+    FlagSpec::new("undo", &[], FlagType::Bool, "", "Restore every entry removed by the most recent hina rm."),
+    FlagSpec::new("batch", &[], FlagType::Str, "ID", "With --undo, restore batch ID, with --list, only list batch ID."),
+    FlagSpec { complete: "dirs", ..FlagSpec::new("to", &[], FlagType::Str, "DIR", "Restore into DIR instead of the original location.") },
+    FlagSpec {
+        default: "skip",
+        choices: &["skip", "rename", "overwrite", "ask"],
+        ..FlagSpec::new("on-conflict", &[], FlagType::Str, "POLICY", "When the destination exists, skip, rename, overwrite or ask.")
+    },
+    FlagSpec::new("purge", &[], FlagType::Bool, "", "Permanently delete the entries selected by the options below."),
+    FlagSpec::new("older-than", &[], FlagType::Str, "AGE", "With --purge, select entries removed more than AGE ago."),
+    FlagSpec::new("keep-size", &[], FlagType::Str, "SIZE", "With --purge, evict the oldest entries until the bin fits in SIZE."),
+    FlagSpec::new("match", &[], FlagType::Str, "PATTERN", "With --purge, select entries whose original path matches PATTERN."),
+    FlagSpec::new("fsck", &[], FlagType::Bool, "", "Check the recycle bins against the records."),
+    FlagSpec::new("adopt", &[], FlagType::Bool, "", "With --fsck, add a record for every orphan."),
+    FlagSpec::new("quarantine", &[], FlagType::Bool, "", "With --fsck, move orphans to the quarantine directory."),
+    FlagSpec::new("drop-dangling", &[], FlagType::Bool, "", "With --fsck, drop records whose file is gone."),
+    FlagSpec::new("empty", &["ept"], FlagType::Bool, "", "Permanently delete all contents of every bin."),
+    FlagSpec::new("shred", &[], FlagType::Bool, "", "With --empty, overwrite regular files before deleting them."),
+    FlagSpec { default: "3", ..FlagSpec::new("passes", &[], FlagType::Uint, "N", "With --shred, overwrite N times.") },
+    FlagSpec::new("zero", &[], FlagType::Bool, "", "With --shred, overwrite with zeros instead of random data."),
+];
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum ConflictPolicy {
+    Skip,
+    Rename,
+    Overwrite,
+    Ask,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Interactive {
+    Never,
+    Once,
+    Always,
+}
+
+#[derive(Debug)]
+struct RemoveOption {
+    force: bool,
+    interactive: Interactive,
+    verbose: bool,
+    one_file_system: bool,
+    preserve_root: bool,
+    preserve_mount: bool,
+    protect_rules: Vec<ProtectRule>,
+    shred: Option<(usize, bool)>,
+}
+
+#[derive(Serialize)]
+struct ListEntry<'a> {
+    index: usize,
+    #[serde(flatten)]
+    record: &'a RMRecord,
+}
+
+#[derive(Debug)]
+struct RestoreOption<'a> {
+    to: Option<PathBuf>,
+    on_conflict: ConflictPolicy,
+    data_path: &'a PathBuf,
+    recycle_path: &'a PathBuf,
+    uid: &'a String,
+}
+
+impl ConflictPolicy {
+    fn from(name: &str) -> Result<ConflictPolicy, HinaError> {
+        debug_fn!(name);
+        match name {
+            "" | "skip" => { Ok(ConflictPolicy::Skip) }
+            "rename" => { Ok(ConflictPolicy::Rename) }
+            "overwrite" => { Ok(ConflictPolicy::Overwrite) }
+            "ask" => { Ok(ConflictPolicy::Ask) }
+            _ => {
+                let err = format!("Unknown conflict policy \'{}\', expect skip, rename, overwrite or ask", name);
+                Err(ConfigParseError(err))
+            }
+        }
+    }
+}
+
+impl Interactive {
+    fn from(when: &str) -> Result<Interactive, HinaError> {
+        debug_fn!(when);
+        match when {
+            "never" | "no" | "none" => { Ok(Interactive::Never) }
+            "once" => { Ok(Interactive::Once) }
+            "" | "always" | "yes" => { Ok(Interactive::Always) }
+            _ => {
+                let err = format!("Unknown interactive mode \'{}\', expect never, once or always", when);
+                Err(ConfigParseError(err))
+            }
+        }
+    }
+}
+
 impl HinaModuleRun for Remove {
+    fn flag_specs(&self) -> Vec<FlagSpec> {
+        REMOVE_FLAGS.to_vec()
+    }
+
     fn run(&self,
            _work_path: &PathBuf,
            _data_path: &PathBuf,
@@ -33,40 +191,495 @@ impl HinaModuleRun for Remove {
         debug_fn!(_work_path,_data_path,_recycle_path,_user,_uid,_flags,_rm_stack,_arg);
         let _help = _flags.parse_bool(vec!["help"]);
         if _help {
-            Remove::print_help()?;
+            Remove::print_help(_flags)?;
             return Ok(());
         }
+        let option = Remove::build_option(_data_path, _recycle_path, _uid, _flags)?;
         match _arg {
-            None => {}
+            None => {
+                if !option.force {
+                    return Err(ConfigParseError(String::from("Missing operand")));
+                }
+            }
             Some(arg) => {
-                let remove_target = get_execute_target(_work_path, &PathBuf::from(arg))?;
-                let mut recycle_bin = _recycle_path.clone();
-                let file_name = func::gen_rand_str(RAND_STR_LEN);
-                recycle_bin.push(file_name.clone());
-
-                let command = String::from(format!("mv \"{}\" \"{}\"", remove_target.display(), recycle_bin.display()));
-                func::execute_command(&command)?;
-                let now: DateTime<Local> = Local::now();
-                _rm_stack.push(RMRecord::from(
-                    recycle_bin.display().to_string(),
-                    remove_target.display().to_string(),
-                    now.format("%Y-%m-%d %H:%M:%S%.3f").to_string(),
-                ));
+                Remove::remove_arg(_work_path, _data_path, _recycle_path, _uid, _rm_stack, arg, &option)?;
             }
         }
         Ok(())
     }
+
+    fn run_batch(&self,
+                 _work_path: &PathBuf,
+                 _data_path: &PathBuf,
+                 _recycle_path: &PathBuf,
+                 _user: &String,
+                 _uid: &String,
+                 _flags: &Flag,
+                 _rm_stack: &mut Vec<RMRecord>,
+                 _args: &Vec<String>,
+    ) -> Result<bool, HinaError> {
+        debug_fn!(_work_path,_data_path,_recycle_path,_user,_uid,_flags,_rm_stack,_args);
+        let _help = _flags.parse_bool(vec!["help"]);
+        if _help || _args.is_empty() {
+            return Ok(false);
+        }
+        let option = Remove::build_option(_data_path, _recycle_path, _uid, _flags)?;
+        if option.interactive == Interactive::Once {
+            // Like coreutils -I, ask once for more than 3 targets or any directory
+            let has_dir = _args.iter().any(|arg| {
+                let path = func::absolute_path(_work_path, &PathBuf::from(arg));
+                fs::symlink_metadata(path).is_ok_and(|meta| meta.is_dir())
+            });
+            let prompt = match (_args.len(), has_dir) {
+                (1, true) => { String::from("remove 1 argument recursively? ") }
+                (count, true) => { format!("remove {} arguments recursively? ", count) }
+                (count, false) if count > 3 => { format!("remove {} arguments? ", count) }
+                _ => { String::new() }
+            };
+            if !prompt.is_empty() && !func::confirm(&prompt)? {
+                return Ok(true);
+            }
+        }
+        let atomic = _flags.parse_bool(vec!["atomic"]);
+        if atomic && option.shred.is_some() {
+            return Err(ConfigParseError(String::from("--atomic can not roll back --shred")));
+        }
+        let origin_len = _rm_stack.len();
+        let mut removed = 0;
+        let mut failures = Vec::new();
+        for arg in _args {
+            let len = _rm_stack.len();
+            match Remove::remove_arg(_work_path, _data_path, _recycle_path, _uid, _rm_stack, arg, &option) {
+                Ok(_) => { removed += _rm_stack.len() - len; }
+                Err(err) => {
+                    failures.push((arg, err));
+                    if atomic {
+                        break;
+                    }
+                }
+            }
+        }
+        if atomic && !failures.is_empty() {
+            let restored = Remove::rollback(_rm_stack, origin_len);
+            println!("Rolled back {} of {} removed targets", restored, removed);
+            removed -= restored;
+        } else if let Some(quota) = *BIN_QUOTA {
+            // Keep what was just removed, only older entries are evicted
+            RecycleBin::auto_purge(_rm_stack, quota, origin_len)?;
+        }
+        Remove::compress_old(_rm_stack)?;
+
+        if _args.len() > 1 || !failures.is_empty() {
+            println!("{} removed, {} failed", removed, failures.len());
+            for (arg, err) in failures.iter() {
+                println!("  {}: {:?}", arg, err);
+            }
+        }
+        if !failures.is_empty() {
+            return Err(BatchError(format!("Failed to remove {} of {} targets", failures.len(), _args.len())));
+        }
+        Ok(true)
+    }
 }
 
 impl Remove {
-    fn print_help() -> Result<(), HinaError> {
+    fn print_help(flags: &Flag) -> Result<(), HinaError> {
+        debug_fn!(flags);
+        func::print_help("rm", "[OPTION]... [PATH]...", flags)
+    }
+
+    fn build_option(data_path: &PathBuf, recycle_path: &PathBuf, uid: &String, flags: &Flag) -> Result<RemoveOption, HinaError> {
+        // Short flags follow coreutils rm, -r and -d are accepted as hina rm always
+        // removes directories as a whole
+        debug_fn!(data_path,recycle_path,uid,flags);
+        let interactive = if flags.parse_bool(vec!["interactive"]) {
+            Interactive::from(&flags.parse_string(vec!["interactive"]))?
+        } else if flags.parse_bool(vec!["i"]) {
+            Interactive::Always
+        } else if flags.parse_bool(vec!["I"]) {
+            Interactive::Once
+        } else {
+            Interactive::Never
+        };
+        let preserve_root = flags.parse_string(vec!["preserve-root"]);
+        if !preserve_root.is_empty() && preserve_root != "all" {
+            let err = format!("Unknown preserve-root mode \'{}\', expect all", preserve_root);
+            return Err(ConfigParseError(err));
+        }
+        Ok(RemoveOption {
+            force: flags.parse_bool(vec!["f", "force"]),
+            interactive,
+            verbose: flags.parse_bool(vec!["v", "verbose"]),
+            one_file_system: flags.parse_bool(vec!["one-file-system"]),
+            preserve_root: !flags.parse_bool(vec!["no-preserve-root"]),
+            preserve_mount: preserve_root == "all",
+            protect_rules: protect::load_rules(data_path, &Remove::get_live_bins(data_path, recycle_path, uid)?)?,
+            shred: Remove::parse_shred(flags)?,
+        })
+    }
+
+    fn get_live_bins(data_path: &PathBuf, recycle_path: &PathBuf, uid: &String) -> Result<Vec<PathBuf>, HinaError> {
+        // Where removed files are kept, wherever bin.path or the backend put them
+        debug_fn!(data_path,recycle_path,uid);
+        let mut bins = vec![data_path.clone(), recycle_path.clone(), store::get_store_path(data_path)];
+        if *TRASH_BACKEND == TrashBackend::Xdg {
+            bins.extend(xdg::get_all_trashes(uid)?);
+        }
+        Ok(bins)
+    }
+
+    fn parse_shred(flags: &Flag) -> Result<Option<(usize, bool)>, HinaError> {
+        // --shred with the number of overwrite passes and whether to write zeros instead of random data
+        debug_fn!(flags);
+        if !flags.parse_bool(vec!["shred"]) {
+            return Ok(None);
+        }
+        let passes = flags.parse_string(vec!["passes"]);
+        let passes = if passes.is_empty() {
+            DEFAULT_SHRED_PASSES
+        } else {
+            match passes.parse() {
+                Ok(passes) if passes > 0 => { passes }
+                _ => { return Err(ConfigParseError(format!("Invalid number of passes \'{}\'", passes))); }
+            }
+        };
+        Ok(Some((passes, flags.parse_bool(vec!["zero"]))))
+    }
+
+    fn remove_arg(work_path: &PathBuf,
+                  data_path: &PathBuf,
+                  recycle_path: &PathBuf,
+                  uid: &String,
+                  rm_stack: &mut Vec<RMRecord>,
+                  arg: &String,
+                  option: &RemoveOption) -> Result<(), HinaError> {
+        debug_fn!(work_path,data_path,recycle_path,uid,arg,option);
+        let remove_target = match get_execute_target(work_path, &PathBuf::from(arg)) {
+            Ok(target) => { target }
+            Err(FileNotExistError(_)) if option.force => { return Ok(()); }
+            Err(err) => { return Err(err); }
+        };
+        Remove::check_target(arg, &remove_target, option)?;
+        let resolved = protect::resolve_path(&remove_target);
+        if let Some(rule) = protect::check(&option.protect_rules, &resolved) {
+            match rule.get_action() {
+                ProtectAction::Deny => {
+                    let err = format!("{} is protected by rule \'deny {}\'", resolved.display(), rule.get_rule());
+                    return Err(ProtectedPathError(err));
+                }
+                ProtectAction::Confirm => {
+                    let prompt = format!("{} is protected by rule \'confirm {}\', remove anyway? [y/N] ", resolved.display(), rule.get_rule());
+                    if !func::confirm(&prompt)? {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        if option.interactive == Interactive::Always {
+            let kind = match fs::symlink_metadata(&remove_target) {
+                Ok(meta) if meta.is_symlink() => { "symbolic link" }
+                Ok(meta) if meta.is_dir() => { "directory" }
+                _ => { "regular file" }
+            };
+            if !func::confirm(&format!("remove {} \'{}\'? ", kind, arg))? {
+                return Ok(());
+            }
+        }
+        if let Some((passes, zero)) = option.shred {
+            // Shredded files skip the recycle bin, there is nothing left to restore
+            let mut linked = Vec::new();
+            let mut failures = Vec::new();
+            let result = func::shred_path(&remove_target, passes, zero, &mut linked, &mut failures);
+            for path in linked.iter() {
+                println!("{} has other hard links, unlinked without shredding", path.display());
+            }
+            for err in failures.iter() {
+                println!("{:?}", err);
+            }
+            result?;
+            if option.verbose {
+                println!("shredded \'{}\'", arg);
+            }
+            return Ok(());
+        }
+        Remove::remove_to_bin(&remove_target, data_path, recycle_path, uid, rm_stack)?;
+        if option.verbose {
+            println!("removed \'{}\'", arg);
+        }
+        Ok(())
+    }
+
+    fn rollback(rm_stack: &mut Vec<RMRecord>, keep_len: usize) -> usize {
+        // Move everything removed after keep_len back, newest first, returns how many were restored
+        debug_fn!(keep_len);
+        let mut restored = 0;
+        let mut kept = Vec::new();
+        while rm_stack.len() > keep_len {
+            let record = rm_stack.pop().unwrap();
+            let src = PathBuf::from(record.get_src());
+            let shared = Remove::blob_refs(rm_stack, record.get_blob()) + Remove::blob_refs(&kept, record.get_blob()) > 0;
+            match Remove::take_payload(&record, shared, &src) {
+                Ok(_) => {
+                    let _ = func::restore_meta(&src, record.get_mode(), record.get_uid(), record.get_gid(), record.get_mtime());
+                    restored += 1;
+                }
+                Err(err) => {
+                    // Keep the record so the file can still be restored by hand
+                    println!("Unable to roll back {}: {:?}", src.display(), err);
+                    kept.push(record);
+                }
+            }
+        }
+        rm_stack.extend(kept.into_iter().rev());
+        restored
+    }
+
+    fn check_target(arg: &String, target: &PathBuf, option: &RemoveOption) -> Result<(), HinaError> {
+        // Refuse what coreutils rm refuses by default
+        debug_fn!(arg,target,option);
+        if matches!(Path::new(arg).components().next_back(), Some(Component::CurDir) | Some(Component::ParentDir)) {
+            let err = format!("Refusing to remove \'.\' or \'..\' directory: skipping \'{}\'", arg);
+            return Err(ProtectedPathError(err));
+        }
+        if option.preserve_root {
+            let home = PathBuf::from(func::get_home()?);
+            let home = home.canonicalize().unwrap_or(home);
+            if target == Path::new("/") || target == &home {
+                let err = format!("It is dangerous to remove \'{}\', use --no-preserve-root to override", target.display());
+                return Err(ProtectedPathError(err));
+            }
+        }
+        let is_dir = fs::symlink_metadata(target).is_ok_and(|meta| meta.is_dir());
+        if !is_dir || !(option.preserve_mount || option.one_file_system) {
+            return Ok(());
+        }
+        let mount_points = func::get_mount_points()?;
+        if option.preserve_mount && mount_points.contains(target) {
+            let err = format!("Skipping \'{}\', since it is a mount point", target.display());
+            return Err(ProtectedPathError(err));
+        }
+        if option.one_file_system {
+            // Moving a tree holding other mounts would copy those filesystems into the bin
+            if let Some(mount_point) = mount_points.iter().find(|mount_point| mount_point.starts_with(target) && *mount_point != target) {
+                let err = format!("Skipping \'{}\', since it contains {} of another filesystem", target.display(), mount_point.display());
+                return Err(ProtectedPathError(err));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn remove_to_bin(remove_target: &PathBuf,
+                         data_path: &PathBuf,
+                         recycle_path: &PathBuf,
+                         uid: &String,
+                         rm_stack: &mut Vec<RMRecord>) -> Result<(), HinaError> {
+        debug_fn!(remove_target,data_path,recycle_path,uid);
+        let meta = match fs::symlink_metadata(remove_target) {
+            Ok(meta) => { meta }
+            Err(err) => { return Err(BadFileError(format!("Unable to open {}: {}", remove_target.display(), err))); }
+        };
+        let size = func::get_size(remove_target);
+        let link_target = match fs::read_link(remove_target) {
+            Ok(link) => { Some(link.display().to_string()) }
+            Err(_) => { None }
+        };
+
+        let now: DateTime<Local> = Local::now();
+        let mut blob = None;
+        let (bin_path, recycle_bin, unfinished) = match *TRASH_BACKEND {
+            TrashBackend::Hina if Remove::can_dedup(&meta, data_path) => {
+                let store = store::get_store_path(data_path);
+                let hash = store::put(&store, remove_target)?;
+                let recycle_bin = store::blob_path(&store, &hash);
+                blob = Some(hash);
+                (store, recycle_bin, None)
+            }
+            TrashBackend::Hina => { Remove::move_to_bin(remove_target, recycle_path, uid)? }
+            TrashBackend::Xdg => { Remove::move_to_xdg_trash(remove_target, uid, &now)? }
+        };
+        let mut record = RMRecord::from(
+            recycle_bin.display().to_string(),
+            remove_target.display().to_string(),
+            now.format(TIME_FORMAT).to_string(),
+            bin_path.display().to_string(),
+        );
+        record.set_meta(&meta, size, link_target);
+        record.set_batch(BATCH_ID.clone());
+        record.set_blob(blob);
+        rm_stack.push(record);
+        match unfinished {
+            Some(err) => { Err(err) }
+            None => { Ok(()) }
+        }
+    }
+
+    fn can_dedup(meta: &Metadata, data_path: &PathBuf) -> bool {
+        // Only regular files on the filesystem of the store, others would need a full copy anyway
+        debug_fn!(data_path);
+        *BIN_STORE == BinStore::Dedup
+            && meta.is_file()
+            && fs::metadata(data_path).is_ok_and(|bin_meta| bin_meta.dev() == meta.dev())
+    }
+
+    fn compress_old(rm_stack: &[RMRecord]) -> Result<(), HinaError> {
+        // Compress blobs whose newest reference is older than BIN_COMPRESS_AFTER
         debug_fn!();
-        execute_command_in_terminal("man", vec!["hina-rm"])?;
+        if *BIN_COMPRESS == Compression::None {
+            return Ok(());
+        }
+        let deadline = match func::time_ago(*BIN_COMPRESS_AFTER) {
+            Ok(deadline) => { deadline }
+            Err(_) => { return Ok(()); }
+        };
+        let mut newest: HashMap<(&String, &String), &String> = HashMap::new();
+        for record in rm_stack.iter() {
+            if let Some(hash) = record.get_blob() {
+                let time = newest.entry((record.get_bin(), hash)).or_insert(record.get_del_time());
+                *time = max(*time, record.get_del_time());
+            }
+        }
+        for ((bin, hash), time) in newest {
+            if func::parse_time(time).is_some_and(|time| time <= deadline) {
+                match store::compress(&PathBuf::from(bin), hash, *BIN_COMPRESS) {
+                    Ok(_) => {}
+                    Err(err) => { debugln!("Unable to compress {}: {:?}", hash, err); }
+                }
+            }
+        }
         Ok(())
     }
+
+    fn blob_refs(rm_stack: &[RMRecord], blob: &Option<String>) -> usize {
+        debug_fn!(blob);
+        rm_stack.iter().filter(|record| record.get_blob() == blob).count()
+    }
+
+    fn take_payload(record: &RMRecord, shared: bool, dst: &PathBuf) -> Result<(), HinaError> {
+        // Bring back the payload of record at dst, a blob other records still share is copied out
+        debug_fn!(record,shared,dst);
+        match record.get_blob() {
+            Some(hash) => { store::take(&PathBuf::from(record.get_bin()), hash, dst, shared) }
+            None => { func::move_path(&PathBuf::from(record.get_file()), dst) }
+        }
+    }
+
+    fn move_to_bin(target: &PathBuf, recycle_path: &PathBuf, uid: &String) -> Result<(PathBuf, PathBuf, Option<HinaError>), HinaError> {
+        // Along with the paths, the error of a copy whose source could not be fully removed,
+        // such a copy is recorded all the same so it can be restored
+        debug_fn!(target,recycle_path,uid);
+        let bin_path = Remove::locate_bin(target, recycle_path, uid);
+        let mut recycle_bin = bin_path.clone();
+        let file_name = func::gen_rand_str(RAND_STR_LEN);
+        recycle_bin.push(file_name.clone());
+        let unfinished = match func::move_path(target, &recycle_bin) {
+            Ok(_) => { None }
+            Err(err @ PartialMoveError(_)) => { Some(err) }
+            Err(err) => { return Err(err); }
+        };
+        Ok((bin_path, recycle_bin, unfinished))
+    }
+
+    fn move_to_xdg_trash(target: &PathBuf, uid: &String, now: &DateTime<Local>) -> Result<(PathBuf, PathBuf, Option<HinaError>), HinaError> {
+        debug_fn!(target,uid);
+        let trash = xdg::locate_trash(target, uid)?;
+        let file_name = xdg::reserve_name(&trash, target, now)?;
+        let mut bin_path = trash.clone();
+        bin_path.push("files");
+        let mut recycle_bin = bin_path.clone();
+        recycle_bin.push(&file_name);
+        let unfinished = match func::move_path(target, &recycle_bin) {
+            Ok(_) => { None }
+            Err(err @ PartialMoveError(_)) => { Some(err) }
+            Err(err) => {
+                // Release the reserved name
+                if let Some(info) = xdg::info_path_of(&recycle_bin) {
+                    let _ = func::remove_path(&info);
+                }
+                return Err(err);
+            }
+        };
+        Ok((bin_path, recycle_bin, unfinished))
+    }
+
+    fn locate_bin(target: &PathBuf, recycle_path: &PathBuf, uid: &String) -> PathBuf {
+        // Prefer a bin on the same filesystem as target so removing is a rename instead of a copy
+        debug_fn!(target,recycle_path,uid);
+        match Remove::locate_mount_bin(target, recycle_path, uid) {
+            Ok(Some(bin)) => { bin }
+            Ok(None) => { recycle_path.clone() }
+            Err(err) => {
+                debugln!("Unable to use a per-filesystem bin for {}, fall back to {}: {:?}", target.display(), recycle_path.display(), err);
+                recycle_path.clone()
+            }
+        }
+    }
+
+    fn locate_mount_bin(target: &PathBuf, recycle_path: &PathBuf, uid: &String) -> Result<Option<PathBuf>, HinaError> {
+        debug_fn!(target,recycle_path,uid);
+        let parent = match target.parent() {
+            Some(parent) => { parent.to_path_buf() }
+            None => { return Ok(None); }
+        };
+        let parent_meta = match fs::metadata(&parent) {
+            Ok(meta) => { meta }
+            Err(err) => { return Err(BadFileError(format!("Unable to open {}: {}", parent.display(), err))); }
+        };
+        let home_bin_meta = match fs::metadata(recycle_path) {
+            Ok(meta) => { meta }
+            Err(err) => { return Err(BadFileError(format!("Unable to open {}: {}", recycle_path.display(), err))); }
+        };
+        if parent_meta.dev() == home_bin_meta.dev() {
+            return Ok(None);
+        }
+
+        let canonical_parent = match parent.canonicalize() {
+            Ok(path) => { path }
+            Err(err) => { return Err(BadFileError(format!("Unable to open {}: {}", parent.display(), err))); }
+        };
+        let mut bin = func::get_mount_point(&canonical_parent)?;
+        bin.push(MOUNT_TRASH);
+        // Shared by all users like /tmp, each user gets a private sub dir
+        Remove::ensure_bin_dir(&bin, 0o1777)?;
+        bin.push(uid);
+        Remove::ensure_bin_dir(&bin, 0o700)?;
+
+        let bin_meta = match fs::symlink_metadata(&bin) {
+            Ok(meta) => { meta }
+            Err(err) => { return Err(BadFileError(format!("Unable to open {}: {}", bin.display(), err))); }
+        };
+        if &bin_meta.uid().to_string() != uid {
+            return Err(BadFileError(format!("{} is not owned by uid {}", bin.display(), uid)));
+        }
+        if bin_meta.dev() != parent_meta.dev() {
+            return Ok(None);
+        }
+        Ok(Some(bin))
+    }
+
+    fn ensure_bin_dir(dir: &PathBuf, mode: u32) -> Result<(), HinaError> {
+        debug_fn!(dir,mode);
+        if fs::symlink_metadata(dir).is_err() {
+            match fs::create_dir(dir) {
+                Ok(_) => {}
+                Err(err) => { return Err(DirCreateError(format!("Unable to create {}: {}", dir.display(), err))); }
+            }
+            match fs::set_permissions(dir, Permissions::from_mode(mode)) {
+                Ok(_) => {}
+                Err(err) => { return Err(DirCreateError(format!("Unable to set mode of {}: {}", dir.display(), err))); }
+            }
+        }
+        match fs::symlink_metadata(dir) {
+            Ok(meta) if meta.is_dir() => { Ok(()) }
+            _ => { Err(BadFileError(format!("{} is not a directory", dir.display()))) }
+        }
+    }
 }
 
 impl HinaModuleRun for RecycleBin {
+    fn flag_specs(&self) -> Vec<FlagSpec> {
+        RECYCLE_BIN_FLAGS.to_vec()
+    }
+
     fn run(&self,
            _work_path: &PathBuf,
            _data_path: &PathBuf,
@@ -80,58 +693,312 @@ impl HinaModuleRun for RecycleBin {
         debug_fn!(_work_path,_data_path,_recycle_path,_user,_uid,_flags,_rm_stack,_arg);
         let _help = _flags.parse_bool(vec!["help"]);
         if _help {
-            RecycleBin::print_help()?;
+            RecycleBin::print_help(_flags)?;
             return Ok(());
         }
         let _list = _flags.parse_bool(vec!["ls", "list"]);
         let _restore = _flags.parse_bool(vec!["rs", "restore"]);
         let _empty = _flags.parse_bool(vec!["ept", "empty"]);
+        let _purge = _flags.parse_bool(vec!["purge"]);
+        let _fsck = _flags.parse_bool(vec!["fsck"]);
         if _list {
-            RecycleBin::show(_rm_stack)?;
+            RecycleBin::show(_work_path, _rm_stack, _flags)?;
             return Ok(());
         }
         if _restore {
-            RecycleBin::restore(_rm_stack)?;
+            let option = RecycleBin::build_restore_option(_work_path, _data_path, _recycle_path, _uid, _flags)?;
+            RecycleBin::restore(_work_path, _rm_stack, _flags, &option)?;
+            return Ok(());
+        }
+        if _purge {
+            RecycleBin::purge(_work_path, _rm_stack, _flags)?;
+            return Ok(());
+        }
+        if _fsck {
+            RecycleBin::fsck(_data_path, _recycle_path, _uid, _rm_stack, _flags)?;
             return Ok(());
         }
         if _empty {
-            RecycleBin::empty(_recycle_path, _rm_stack)?;
+            RecycleBin::empty(_data_path, _recycle_path, _uid, _rm_stack, _flags)?;
             return Ok(());
         }
         Ok(())
     }
+
+    fn run_batch(&self,
+                 _work_path: &PathBuf,
+                 _data_path: &PathBuf,
+                 _recycle_path: &PathBuf,
+                 _user: &String,
+                 _uid: &String,
+                 _flags: &Flag,
+                 _rm_stack: &mut Vec<RMRecord>,
+                 _args: &Vec<String>,
+    ) -> Result<bool, HinaError> {
+        debug_fn!(_work_path,_data_path,_recycle_path,_user,_uid,_flags,_rm_stack,_args);
+        let _help = _flags.parse_bool(vec!["help"]);
+        let _restore = _flags.parse_bool(vec!["rs", "restore"]);
+        let _last = _flags.parse_bool(vec!["last"]);
+        let _undo = _flags.parse_bool(vec!["undo"]);
+        // Without targets restore falls back to the interactive mode in run
+        if _help || !(_last || _undo || (_restore && !_args.is_empty())) {
+            return Ok(false);
+        }
+        let option = RecycleBin::build_restore_option(_work_path, _data_path, _recycle_path, _uid, _flags)?;
+        let mut indices = Vec::new();
+        if _undo {
+            indices.append(&mut RecycleBin::resolve_batch(_rm_stack, &_flags.parse_string(vec!["batch"]))?);
+        }
+        if _last {
+            let count = max(_flags.parse_uint(vec!["last"]), 1);
+            indices.extend(_rm_stack.len().saturating_sub(count).._rm_stack.len());
+        }
+        for arg in _args {
+            match RecycleBin::resolve_target(_work_path, _rm_stack, arg) {
+                Ok(mut target_indices) => { indices.append(&mut target_indices); }
+                Err(err) => { println!("{:?}", err); }
+            }
+        }
+        RecycleBin::restore_indices(_rm_stack, indices, &option)?;
+        Ok(true)
+    }
 }
 
 impl RecycleBin {
-    fn print_help() -> Result<(), HinaError> {
-        debug_fn!();
-        execute_command_in_terminal("man", vec!["hina-rb"])?;
-        Ok(())
+    fn print_help(flags: &Flag) -> Result<(), HinaError> {
+        debug_fn!(flags);
+        func::print_help("rb", "[OPTION]... [TARGET]...", flags)
     }
 
-    fn show(rm_stack: &Vec<RMRecord>) -> Result<(), HinaError> {
-        debug_fn!(rm_stack);
-        for (i, record) in rm_stack.iter().enumerate() {
-            println!("{}: File: {}, delete-time: {}", i, record.get_src(), record.get_del_time());
+    fn show(work_path: &PathBuf, rm_stack: &Vec<RMRecord>, flags: &Flag) -> Result<(), HinaError> {
+        debug_fn!(work_path,rm_stack,flags);
+        let sort_by = flags.parse_string(vec!["sort-by"]);
+        let filter = flags.parse_string(vec!["filter"]);
+        let since = flags.parse_string(vec!["since"]);
+        let until = flags.parse_string(vec!["until"]);
+        let mut format = flags.parse_string(vec!["format"]);
+        if format.is_empty() {
+            format = func::get_settings()?.output.format.clone();
+        }
+        let batch = flags.parse_string(vec!["batch"]);
+
+        let mut indices: Vec<usize> = (0..rm_stack.len()).collect();
+        if !filter.is_empty() {
+            let pattern = func::build_glob(work_path, &filter)?;
+            indices.retain(|&i| func::glob_match(&pattern, rm_stack[i].get_src()));
+        }
+        if !batch.is_empty() {
+            indices.retain(|&i| rm_stack[i].get_batch() == &batch);
+        }
+        if !since.is_empty() {
+            let since = func::parse_time_point(&since)?;
+            indices.retain(|&i| func::parse_time(rm_stack[i].get_del_time()).is_some_and(|time| time >= since));
+        }
+        if !until.is_empty() {
+            let until = func::parse_time_point(&until)?;
+            indices.retain(|&i| func::parse_time(rm_stack[i].get_del_time()).is_some_and(|time| time <= until));
+        }
+
+        let sizes: HashMap<usize, u64> = indices
+            .iter()
+            .map(|&i| (i, RecycleBin::record_size(&rm_stack[i])))
+            .collect();
+        match sort_by.as_str() {
+            // A batch is sorted by its first deletion time, so its entries stay together
+            "" | "time" => {
+                let mut batch_time: HashMap<&String, &String> = HashMap::new();
+                for &i in indices.iter() {
+                    let time = batch_time.entry(rm_stack[i].get_batch()).or_insert(rm_stack[i].get_del_time());
+                    *time = min(*time, rm_stack[i].get_del_time());
+                }
+                let key = |i: usize| {
+                    let record = &rm_stack[i];
+                    let start = if record.get_batch().is_empty() { record.get_del_time() } else { batch_time[record.get_batch()] };
+                    (start, record.get_batch(), record.get_del_time())
+                };
+                indices.sort_by(|&x, &y| key(x).cmp(&key(y)));
+            }
+            "size" => { indices.sort_by_key(|i| sizes[i]); }
+            "path" => { indices.sort_by(|&x, &y| rm_stack[x].get_src().cmp(rm_stack[y].get_src())); }
+            _ => {
+                let err = format!("Unknown sort key \'{}\', expect time, size or path", sort_by);
+                return Err(ConfigParseError(err));
+            }
+        }
+
+        match format.as_str() {
+            "" | "table" => {
+                let head = vec!["BATCH".to_string(),
+                                "INDEX".to_string(),
+                                "PATH".to_string(),
+                                "TYPE".to_string(),
+                                "SIZE".to_string(),
+                                "DELETED AT".to_string(),
+                                "AGE".to_string()];
+                let now = Local::now().naive_local();
+                let mut output_list = Vec::new();
+                let mut last_batch = None;
+                for i in indices {
+                    let record = &rm_stack[i];
+                    // Name each batch once at its first entry
+                    let batch = match (record.get_batch().as_str(), last_batch) {
+                        ("", _) => { String::from("-") }
+                        (batch, Some(last)) if batch == last => { String::new() }
+                        (batch, _) => { batch.to_string() }
+                    };
+                    last_batch = Some(record.get_batch().as_str());
+                    let file_type = match (record.get_file_type().as_str(), record.get_link_target()) {
+                        ("", _) => { String::from("-") }
+                        ("symlink", Some(link)) => { format!("symlink -> {}", link) }
+                        (file_type, _) => { file_type.to_string() }
+                    };
+                    let age = match func::parse_time(record.get_del_time()) {
+                        Some(time) => { func::format_age(now - time) }
+                        None => { String::from("-") }
+                    };
+                    output_list.push(vec![batch,
+                                          i.to_string(),
+                                          func::display_path(work_path, record.get_src()),
+                                          file_type,
+                                          func::format_size(sizes[&i]),
+                                          record.get_del_time().chars().take(19).collect(),
+                                          age]);
+                }
+                print_info(&head, &output_list, 7);
+            }
+            "json" => {
+                let entries: Vec<ListEntry> = indices
+                    .into_iter()
+                    .map(|i| ListEntry { index: i, record: &rm_stack[i] })
+                    .collect();
+                match serde_json::to_string_pretty(&entries) {
+                    Ok(output) => { println!("{}", output); }
+                    Err(err) => { return Err(FileWriteError(err.to_string())); }
+                }
+            }
+            _ => {
+                let err = format!("Unknown format \'{}\', expect table or json", format);
+                return Err(ConfigParseError(err));
+            }
         }
         Ok(())
     }
 
-    fn restore(_rm_stack: &mut Vec<RMRecord>) -> Result<(), HinaError> {
-        debug_fn!(_rm_stack);
-        RecycleBin::show(_rm_stack)?;
+    fn record_size(record: &RMRecord) -> u64 {
+        // Records written before sizes were recorded are measured on the spot
+        debug_fn!(record);
+        match record.get_size() {
+            Some(size) => { size }
+            None => { func::get_size(&PathBuf::from(record.get_file())) }
+        }
+    }
 
-        let mut input = String::new();
-        stdin().read_line(&mut input).unwrap();
+    fn build_restore_option<'a>(work_path: &PathBuf,
+                                data_path: &'a PathBuf,
+                                recycle_path: &'a PathBuf,
+                                uid: &'a String,
+                                flags: &Flag) -> Result<RestoreOption<'a>, HinaError> {
+        debug_fn!(work_path,data_path,recycle_path,uid,flags);
+        let to = flags.parse_string(vec!["to"]);
+        let on_conflict = flags.parse_string(vec!["on-conflict"]);
+        Ok(RestoreOption {
+            to: if to.is_empty() { None } else { Some(func::absolute_path(work_path, &PathBuf::from(to))) },
+            on_conflict: ConflictPolicy::from(&on_conflict)?,
+            data_path,
+            recycle_path,
+            uid,
+        })
+    }
 
-        let indices_str = split_and_remove_blank(&input, ",")?;
-        let indices: Vec<_> = indices_str
-            .into_iter()
-            .filter_map(|s| s.parse().ok())
+    fn restore(work_path: &PathBuf,
+               _rm_stack: &mut Vec<RMRecord>,
+               flags: &Flag,
+               option: &RestoreOption) -> Result<(), HinaError> {
+        debug_fn!(work_path,_rm_stack,option);
+        RecycleBin::show(work_path, _rm_stack, flags)?;
+
+        let input = func::read_input("")?;
+
+        let targets = split_and_remove_blank(&input, ",")?;
+        let mut indices = Vec::new();
+        for target in targets {
+            match RecycleBin::resolve_target(work_path, _rm_stack, &target.trim().to_string()) {
+                Ok(mut target_indices) => { indices.append(&mut target_indices); }
+                Err(err) => { println!("{:?}", err); }
+            }
+        }
+        RecycleBin::restore_indices(_rm_stack, indices, option)?;
+        return Ok(());
+    }
+
+    fn resolve_target(work_path: &PathBuf, rm_stack: &[RMRecord], target: &String) -> Result<Vec<usize>, HinaError> {
+        // A target is an index, an index range like 3-7, an original path or a glob of original paths
+        debug_fn!(work_path,target);
+        if let Some((start, end)) = func::parse_index_range(target) {
+            // Checked before collecting, a range like 0-99999999999 would not fit in memory
+            return match rm_stack.len() {
+                0 => { Err(OutOfIndexError(format!("Index {} is out of recycle bin, it is empty", target))) }
+                len if end >= len => { Err(OutOfIndexError(format!("Index {} is out of recycle bin, expect 0 to {}", target, len - 1))) }
+                _ => { Ok((start..=end).collect()) }
+            };
+        }
+        if func::is_glob(target) {
+            let pattern = func::build_glob(work_path, target)?;
+            let indices: Vec<usize> = rm_stack
+                .iter()
+                .enumerate()
+                .filter(|(_, record)| func::glob_match(&pattern, record.get_src()))
+                .map(|(i, _)| i)
+                .collect();
+            if !indices.is_empty() {
+                return Ok(indices);
+            }
+        } else {
+            let path = func::absolute_path(work_path, &PathBuf::from(target));
+            // The same path may be removed several times, restore the newest one
+            let index = rm_stack
+                .iter()
+                .rposition(|record| Path::new(record.get_src()) == path);
+            if let Some(index) = index {
+                return Ok(vec![index]);
+            }
+        }
+        Err(FileNotExistError(format!("No record in recycle bin matches {}", target)))
+    }
+
+    fn resolve_batch(rm_stack: &[RMRecord], batch: &String) -> Result<Vec<usize>, HinaError> {
+        // Entries of the given batch, or of the most recent one when batch is empty
+        debug_fn!(batch);
+        let batch = if batch.is_empty() {
+            match rm_stack.iter().max_by(|x, y| x.get_del_time().cmp(y.get_del_time())) {
+                Some(record) => { record.get_batch().clone() }
+                None => { return Err(FileNotExistError(String::from("Recycle bin is empty, nothing to undo"))); }
+            }
+        } else {
+            batch.clone()
+        };
+        if batch.is_empty() {
+            // Records written before batches were recorded are undone one by one
+            let index = (0..rm_stack.len()).max_by(|&x, &y| rm_stack[x].get_del_time().cmp(rm_stack[y].get_del_time()));
+            return Ok(index.into_iter().collect());
+        }
+        let indices: Vec<usize> = (0..rm_stack.len())
+            .filter(|&i| rm_stack[i].get_batch() == &batch)
             .collect();
-        let removed: Vec<_> = indices
+        if indices.is_empty() {
+            return Err(FileNotExistError(format!("No record in recycle bin belongs to batch {}", batch)));
+        }
+        Ok(indices)
+    }
+
+    fn restore_indices(rm_stack: &mut Vec<RMRecord>, indices: Vec<usize>, option: &RestoreOption) -> Result<(), HinaError> {
+        debug_fn!(indices,option);
+        let mut indices = indices;
+        indices.sort();
+        indices.dedup();
+        let restored: Vec<_> = indices
             .into_iter()
-            .filter_map(|i| match RecycleBin::restore_index(_rm_stack, i) {
+            .filter_map(|i| match RecycleBin::restore_index(rm_stack, i, option) {
                 Ok(i) => { Some(i) }
                 Err(err) => {
                     println!("{:?}", err);
@@ -139,37 +1006,456 @@ impl RecycleBin {
                 }
             })
             .collect();
-        for i in removed.into_iter().rev() {
-            if i < _rm_stack.len() {
-                _rm_stack.remove(i);
-            }
+        // Remove from the back so the remaining indices stay valid, records of
+        // overwritten files are appended after them and are not affected
+        for i in restored.into_iter().rev() {
+            rm_stack.remove(i);
         }
-        return Ok(());
+        Ok(())
     }
 
-    fn restore_index(rm_stack: &Vec<RMRecord>, index: usize) -> Result<usize, HinaError> {
-        debug_fn!(rm_stack,index);
+    fn restore_index(rm_stack: &mut Vec<RMRecord>, index: usize, option: &RestoreOption) -> Result<usize, HinaError> {
+        debug_fn!(rm_stack,index,option);
         if index >= rm_stack.len() {
             return Err(OutOfIndexError(format!("Index {} is out of recycle bin", index)));
         }
+        let file = PathBuf::from(rm_stack[index].get_file());
+        let src = PathBuf::from(rm_stack[index].get_src());
+        let unknown_origin = rm_stack[index].get_src() == UNKNOWN_ORIGIN;
+        if unknown_origin && option.to.is_none() {
+            return Err(FileNotExistError(format!("{} has an unknown origin, restore it with --to", file.display())));
+        }
+        let mut dst = match &option.to {
+            Some(dir) => {
+                let mut dst = dir.clone();
+                let name = if unknown_origin { file.file_name() } else { src.file_name() };
+                match name {
+                    Some(name) => { dst.push(name); }
+                    None => { return Err(BadFileError(format!("Unable to restore {} into {}", src.display(), dir.display()))); }
+                }
+                dst
+            }
+            None => { src.clone() }
+        };
+
+        if fs::symlink_metadata(&dst).is_ok() {
+            let policy = match option.on_conflict {
+                ConflictPolicy::Ask => { RecycleBin::ask_conflict_policy(&dst)? }
+                policy => { policy }
+            };
+            match policy {
+                ConflictPolicy::Rename => { dst = RecycleBin::gen_free_path(&dst); }
+                ConflictPolicy::Overwrite => {
+                    // The overwritten file goes to the recycle bin as well, so nothing is lost
+                    Remove::remove_to_bin(&dst, option.data_path, option.recycle_path, option.uid, rm_stack)?;
+                }
+                _ => { return Err(FileExistError(format!("{} already exists, skip restoring", dst.display()))); }
+            }
+        }
+
+        match dst.parent() {
+            Some(parent) if !parent.exists() => {
+                match fs::create_dir_all(parent) {
+                    Ok(_) => { println!("{} recreated", parent.display()); }
+                    Err(err) => { return Err(DirCreateError(format!("Unable to create {}: {}", parent.display(), err))); }
+                }
+            }
+            _ => {}
+        }
+        let shared = Remove::blob_refs(rm_stack, rm_stack[index].get_blob()) > 1;
+        Remove::take_payload(&rm_stack[index], shared, &dst)?;
         let record = &rm_stack[index];
-        let src = PathBuf::from(record.get_src());
-        if src.exists() {
-            Err(FileExistError(format!("{} already exists, cannot restore", record.get_src())))
-        } else {
-            let command = String::from(format!("mv \"{}\" \"{}\"", record.get_file(), record.get_src()));
-            func::execute_command(&command)?;
-            println!("{} restored", record.get_src());
-            Ok(index)
+        let restored = if dst == src { src.display().to_string() } else { format!("{} to {}", src.display(), dst.display()) };
+        // The payload is back at dst, so the record has to go even when the metadata can not follow,
+        // e.g. the mtime of a file owned by someone else or of a mode 000 file as non-root
+        match func::restore_meta(&dst, record.get_mode(), record.get_uid(), record.get_gid(), record.get_mtime()) {
+            Ok(_) => { println!("{} restored", restored); }
+            Err(err) => { println!("{} restored, but could not restore mode/mtime: {:?}", restored, err); }
+        }
+        Ok(index)
+    }
+
+    fn ask_conflict_policy(dst: &PathBuf) -> Result<ConflictPolicy, HinaError> {
+        debug_fn!(dst);
+        loop {
+            let prompt = format!("{} already exists, [s]kip, [r]ename or [o]verwrite? ", dst.display());
+            match func::read_input(&prompt)?.trim() {
+                "s" | "skip" => { return Ok(ConflictPolicy::Skip); }
+                "r" | "rename" => { return Ok(ConflictPolicy::Rename); }
+                "o" | "overwrite" => { return Ok(ConflictPolicy::Overwrite); }
+                _ => {}
+            }
+        }
+    }
+
+    fn gen_free_path(path: &PathBuf) -> PathBuf {
+        // Add the first free numeric suffix, a.txt -> a.txt.1
+        debug_fn!(path);
+        let mut index = 1;
+        loop {
+            let candidate = PathBuf::from(format!("{}.{}", path.display(), index));
+            if fs::symlink_metadata(&candidate).is_err() {
+                return candidate;
+            }
+            index += 1;
+        }
+    }
+
+    fn purge(work_path: &PathBuf, rm_stack: &mut Vec<RMRecord>, flags: &Flag) -> Result<(), HinaError> {
+        debug_fn!(work_path,rm_stack,flags);
+        let older_than = flags.parse_string(vec!["older-than"]);
+        let keep_size = flags.parse_string(vec!["keep-size"]);
+        let pattern = flags.parse_string(vec!["match"]);
+        if older_than.is_empty() && keep_size.is_empty() && pattern.is_empty() {
+            let err = String::from("Purge needs at least one of --older-than, --keep-size and --match");
+            return Err(ConfigParseError(err));
+        }
+
+        let mut candidates: Vec<usize> = (0..rm_stack.len()).collect();
+        if !pattern.is_empty() {
+            let pattern = func::build_glob(work_path, &pattern)?;
+            candidates.retain(|&i| func::glob_match(&pattern, rm_stack[i].get_src()));
+        }
+        if !older_than.is_empty() {
+            let deadline = func::time_ago(func::parse_duration(&older_than)?)?;
+            candidates.retain(|&i| match func::parse_time(rm_stack[i].get_del_time()) {
+                Some(delete_time) => { delete_time <= deadline }
+                None => { false }
+            });
+        }
+        if !keep_size.is_empty() {
+            let quota = func::parse_size(&keep_size)?;
+            candidates = RecycleBin::select_over_quota(rm_stack, candidates, quota);
+        }
+        RecycleBin::purge_indices(rm_stack, candidates)?;
+        Ok(())
+    }
+
+    fn auto_purge(rm_stack: &mut Vec<RMRecord>, quota: u64, keep_from: usize) -> Result<(), HinaError> {
+        // Evict the oldest entries before keep_from until the recycle bin fits in quota
+        debug_fn!(quota,keep_from);
+        let candidates = RecycleBin::select_over_quota(rm_stack, (0..keep_from).collect(), quota);
+        if !candidates.is_empty() {
+            println!("Recycle bin is over quota {}, purging the oldest entries", func::format_size(quota));
+            RecycleBin::purge_indices(rm_stack, candidates)?;
+        }
+        Ok(())
+    }
+
+    fn select_over_quota(rm_stack: &[RMRecord], candidates: Vec<usize>, quota: u64) -> Vec<usize> {
+        // Pick candidates oldest first until the whole bin fits in quota
+        debug_fn!(candidates,quota);
+        let sizes: Vec<u64> = rm_stack
+            .iter()
+            .map(RecycleBin::record_size)
+            .collect();
+        let mut total: u64 = sizes.iter().sum();
+        let mut candidates = candidates;
+        candidates.sort_by(|&x, &y| rm_stack[x].get_del_time().cmp(rm_stack[y].get_del_time()));
+        let mut selected = Vec::new();
+        for i in candidates {
+            if total <= quota {
+                break;
+            }
+            total -= sizes[i];
+            selected.push(i);
+        }
+        selected
+    }
+
+    fn purge_indices(rm_stack: &mut Vec<RMRecord>, indices: Vec<usize>) -> Result<(), HinaError> {
+        debug_fn!(indices);
+        let mut indices = indices;
+        indices.sort();
+        indices.dedup();
+        let mut count = 0;
+        let mut freed = 0;
+        for i in indices.into_iter().rev() {
+            if let Some(hash) = rm_stack[i].get_blob().clone() {
+                // A blob is only deleted with the last record referring to it
+                let record = rm_stack.remove(i);
+                if Remove::blob_refs(rm_stack, record.get_blob()) == 0 {
+                    match store::release(&PathBuf::from(record.get_bin()), &hash) {
+                        Ok(size) => { freed += size; }
+                        Err(err) => { println!("{:?}", err); }
+                    }
+                }
+                debugln!("{} purged", record.get_src());
+                count += 1;
+                continue;
+            }
+            let file = PathBuf::from(rm_stack[i].get_file());
+            let size = func::get_size(&file);
+            if fs::symlink_metadata(&file).is_ok() {
+                match func::remove_path(&file) {
+                    Ok(_) => {}
+                    Err(err) => {
+                        println!("{:?}", err);
+                        continue;
+                    }
+                }
+            }
+            debugln!("{} purged", rm_stack[i].get_src());
+            rm_stack.remove(i);
+            count += 1;
+            freed += size;
+        }
+        println!("Purged {} entries, freed {}", count, func::format_size(freed));
+        Ok(())
+    }
+
+    fn fsck(data_path: &PathBuf,
+            recycle_path: &PathBuf,
+            uid: &String,
+            rm_stack: &mut Vec<RMRecord>,
+            flags: &Flag) -> Result<(), HinaError> {
+        // Reconcile the bins with the records, orphans are files without a record and
+        // dangling records are records whose file is gone
+        debug_fn!(data_path,recycle_path,uid,flags);
+        let mut adopt = flags.parse_bool(vec!["adopt"]);
+        let mut quarantine = flags.parse_bool(vec!["quarantine"]);
+        let mut drop_dangling = flags.parse_bool(vec!["drop-dangling"]);
+        if adopt && quarantine {
+            return Err(ConfigParseError(String::from("--adopt and --quarantine can not be used together")));
+        }
+        // Orphans of a FreeDesktop trash have no .trashinfo to rebuild a record from
+        let can_adopt = *TRASH_BACKEND == TrashBackend::Hina;
+        if adopt && !can_adopt {
+            return Err(ConfigParseError(String::from("--adopt is only supported by the hina trash backend")));
+        }
+
+        let bins = RecycleBin::collect_bins(recycle_path, uid, rm_stack)?;
+        let known: HashSet<PathBuf> = rm_stack.iter().map(|record| PathBuf::from(record.get_file())).collect();
+        let mut orphans = Vec::new();
+        for bin in bins.iter() {
+            let entries = match bin.read_dir() {
+                Ok(entries) => { entries }
+                Err(err) => { return Err(DirReadError(format!("Unable to read {}: {}", bin.display(), err))); }
+            };
+            for entry in entries.flatten() {
+                if !known.contains(&entry.path()) {
+                    orphans.push((bin.clone(), entry.path()));
+                }
+            }
+        }
+        orphans.sort();
+        // The store is laid out by hash, its orphans are blobs no record refers to
+        let referenced: HashSet<String> = rm_stack.iter().filter_map(|record| record.get_blob().clone()).collect();
+        let mut stores = vec![store::get_store_path(data_path)];
+        for record in rm_stack.iter().filter(|record| record.get_blob().is_some()) {
+            let store = PathBuf::from(record.get_bin());
+            if !stores.contains(&store) {
+                stores.push(store);
+            }
+        }
+        let mut orphan_blobs = Vec::new();
+        for store in stores.iter() {
+            orphan_blobs.append(&mut store::find_orphans(store, &referenced)?);
+        }
+        let dangling: Vec<usize> = (0..rm_stack.len())
+            .filter(|&i| !RecycleBin::payload_exists(&rm_stack[i]))
+            .collect();
+
+        for (_, orphan) in orphans.iter() {
+            println!("{} {} ({})", "orphan".yellow(), orphan.display(), func::format_size(func::get_size(orphan)));
+        }
+        for blob in orphan_blobs.iter() {
+            println!("{} {} ({})", "orphan blob".yellow(), blob.display(), func::format_size(func::get_size(blob)));
+        }
+        for &i in dangling.iter() {
+            println!("{} [{}] {} -> {}", "dangling".yellow(), i, rm_stack[i].get_src(), rm_stack[i].get_file());
+        }
+
+        if !(adopt || quarantine || drop_dangling) {
+            if !orphans.is_empty() || !orphan_blobs.is_empty() {
+                // Blobs have no name or type left to adopt them with
+                let prompt = if can_adopt && !orphans.is_empty() {
+                    format!("{} orphans, [a]dopt, [q]uarantine or [s]kip? ", orphans.len() + orphan_blobs.len())
+                } else {
+                    format!("{} orphans, [q]uarantine or [s]kip? ", orphans.len() + orphan_blobs.len())
+                };
+                loop {
+                    match func::read_input(&prompt)?.trim() {
+                        "a" | "adopt" if can_adopt => { adopt = true; }
+                        "q" | "quarantine" => { quarantine = true; }
+                        "s" | "skip" => {}
+                        _ => { continue; }
+                    }
+                    break;
+                }
+            }
+            if !dangling.is_empty() {
+                let prompt = format!("Drop {} dangling records? [y/N] ", dangling.len());
+                drop_dangling = matches!(func::read_input(&prompt)?.trim(), "y" | "yes");
+            }
+        }
+
+        let mut dropped = 0;
+        if drop_dangling {
+            for &i in dangling.iter().rev() {
+                debugln!("{} dropped", rm_stack[i].get_src());
+                rm_stack.remove(i);
+                dropped += 1;
+            }
+        }
+        let mut adopted = 0;
+        let mut quarantined = 0;
+        if adopt {
+            let now: DateTime<Local> = Local::now();
+            for (bin, orphan) in orphans.iter() {
+                let meta = match fs::symlink_metadata(orphan) {
+                    Ok(meta) => { meta }
+                    Err(err) => {
+                        println!("{:?}", BadFileError(format!("Unable to open {}: {}", orphan.display(), err)));
+                        continue;
+                    }
+                };
+                let link_target = fs::read_link(orphan).ok().map(|link| link.display().to_string());
+                let mut record = RMRecord::from(
+                    orphan.display().to_string(),
+                    UNKNOWN_ORIGIN.to_string(),
+                    now.format(TIME_FORMAT).to_string(),
+                    bin.display().to_string(),
+                );
+                record.set_meta(&meta, func::get_size(orphan), link_target);
+                rm_stack.push(record);
+                adopted += 1;
+            }
+        }
+        if quarantine && !(orphans.is_empty() && orphan_blobs.is_empty()) {
+            let mut quarantine_path = data_path.clone();
+            quarantine_path.push(QUARANTINE);
+            Remove::ensure_bin_dir(&quarantine_path, 0o700)?;
+            for orphan in orphans.iter().map(|(_, orphan)| orphan).chain(orphan_blobs.iter()) {
+                let mut dst = quarantine_path.clone();
+                dst.push(orphan.file_name().unwrap());
+                if fs::symlink_metadata(&dst).is_ok() {
+                    dst = RecycleBin::gen_free_path(&dst);
+                }
+                match func::move_path(orphan, &dst) {
+                    Ok(_) => { quarantined += 1; }
+                    Err(err) => { println!("{:?}", err); }
+                }
+            }
         }
+
+        println!("Checked {} records in {} bins: {} orphans and {} orphan blobs ({} adopted, {} quarantined), {} dangling records ({} dropped)",
+                 known.len(), bins.len(), orphans.len(), orphan_blobs.len(), adopted, quarantined, dangling.len(), dropped);
+        Ok(())
     }
 
-    fn empty(recycle_path: &PathBuf, rm_stack: &mut Vec<RMRecord>) -> Result<(), HinaError> {
-        debug_fn!(recycle_path,rm_stack);
-        let command = String::from(format!("rm -rf {}/*", recycle_path.display()));
-        func::execute_command(&command)?;
-        rm_stack.clear();
-        println!("Recycle bin emptied");
+    fn collect_bins(recycle_path: &Path, uid: &String, rm_stack: &[RMRecord]) -> Result<Vec<PathBuf>, HinaError> {
+        // Every bin that may hold removed files, including per-filesystem bins no record points to anymore
+        debug_fn!(recycle_path,uid);
+        let mut bins = Vec::new();
+        match *TRASH_BACKEND {
+            TrashBackend::Hina => {
+                bins.push(recycle_path.to_path_buf());
+                for mount_point in func::get_mount_points()? {
+                    let mut bin = mount_point;
+                    bin.push(MOUNT_TRASH);
+                    bin.push(uid);
+                    bins.push(bin);
+                }
+            }
+            TrashBackend::Xdg => {
+                for trash in xdg::get_all_trashes(uid)? {
+                    let mut bin = trash;
+                    bin.push("files");
+                    bins.push(bin);
+                }
+            }
+        }
+        // The dedup store is laid out by hash, not a bin to look for orphans in
+        for record in rm_stack.iter().filter(|record| record.get_blob().is_none()) {
+            // Records written before bins were recorded live next to their file
+            let bin = match record.get_bin().is_empty() {
+                true => { Path::new(record.get_file()).parent().map(|parent| parent.to_path_buf()) }
+                false => { Some(PathBuf::from(record.get_bin())) }
+            };
+            if let Some(bin) = bin {
+                bins.push(bin);
+            }
+        }
+        let mut checked = Vec::new();
+        for bin in bins {
+            if bin.is_dir() && !checked.contains(&bin) {
+                checked.push(bin);
+            }
+        }
+        Ok(checked)
+    }
+
+    fn payload_exists(record: &RMRecord) -> bool {
+        debug_fn!(record);
+        match record.get_blob() {
+            Some(hash) => { store::find_blob(&PathBuf::from(record.get_bin()), hash).is_some() }
+            None => { fs::symlink_metadata(record.get_file()).is_ok() }
+        }
+    }
+
+    fn empty(data_path: &PathBuf, recycle_path: &PathBuf, uid: &String, rm_stack: &mut Vec<RMRecord>, flags: &Flag) -> Result<(), HinaError> {
+        // Remove everything in every bin, dotfiles and orphans included
+        debug_fn!(data_path,recycle_path,uid,rm_stack,flags);
+        let shred = Remove::parse_shred(flags)?;
+        let mut bins = RecycleBin::collect_bins(recycle_path, uid, rm_stack)?;
+        let store = store::get_store_path(data_path);
+        if store.is_dir() {
+            bins.push(store.clone());
+        }
+        // The store is counted by its blobs, its two hex digit directories are removed once emptied
+        let mut paths = Vec::new();
+        let mut store_dirs = Vec::new();
+        for bin in bins.iter() {
+            let entries = match bin.read_dir() {
+                Ok(entries) => { entries }
+                Err(err) => { return Err(DirReadError(format!("Unable to read {}: {}", bin.display(), err))); }
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if *bin != store || !path.is_dir() {
+                    paths.push(path);
+                    continue;
+                }
+                match path.read_dir() {
+                    Ok(blobs) => { paths.extend(blobs.flatten().map(|blob| blob.path())); }
+                    Err(err) => { println!("{:?}", DirReadError(format!("Unable to read {}: {}", path.display(), err))); }
+                }
+                store_dirs.push(path);
+            }
+        }
+        let mut count = 0;
+        let mut freed = 0;
+        let mut linked = Vec::new();
+        for path in paths.iter() {
+            let size = func::get_size(path);
+            let mut failures = Vec::new();
+            let result = match shred {
+                Some((passes, zero)) => { func::shred_path(path, passes, zero, &mut linked, &mut failures) }
+                None => { func::remove_path(path) }
+            };
+            for err in failures.iter() {
+                println!("{:?}", err);
+            }
+            match result {
+                Ok(_) => {
+                    count += 1;
+                    freed += size;
+                }
+                Err(err) => { println!("{:?}", err); }
+            }
+        }
+        for dir in store_dirs.iter() {
+            // Only empty ones go, a blob that could not be removed keeps its directory
+            let _ = fs::remove_dir(dir);
+        }
+        // Keep the records of whatever could not be removed
+        rm_stack.retain(RecycleBin::payload_exists);
+        for path in linked.iter() {
+            println!("{} has other hard links, unlinked without shredding", path.display());
+        }
+        let action = if shred.is_some() { "shredded" } else { "emptied" };
+        println!("Recycle bin {}, removed {} entries, freed {}", action, count, func::format_size(freed));
         Ok(())
     }
 }
\ No newline at end of file
@@ -6,6 +6,7 @@ use crate::core::config::Config;
 use crate::core::error::HinaError;
 use crate::core::executor::Executor;
 use crate::core::global::DEBUG;
+use crate::event::completion::Completion;
 
 mod core;
 mod event;
@@ -14,6 +15,12 @@ fn main() -> Result<(), HinaError> {
     // Collect args
     let arg: Vec<String> = env::args().collect();
 
+    // Completion scripts are printed before anything touches the settings or the data dir
+    if let Some(script) = Completion::generate(&arg) {
+        print!("{}", script?);
+        return Ok(());
+    }
+
     // Build config
     let config = Config::build(&arg)?;
 
@@ -1,2 +1,7 @@
 pub mod test_rm;
+pub mod test_func;
+pub mod test_config;
+pub mod test_protect;
+pub mod test_process;
+pub mod test_store;
 pub mod consts;
\ No newline at end of file
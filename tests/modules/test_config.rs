@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+
+use hina::core::config::Config;
+use hina::core::global::MODULE_MAP;
+use hina::core::settings::{merge_table, Settings};
+use hina::event::completion::Completion;
+use toml::Table;
+
+fn to_args(input: Vec<&str>) -> Vec<String> {
+    input.into_iter().map(String::from).collect()
+}
+
+#[test]
+fn parse_combined_short_flags() {
+    let config = Config::build(&to_args(vec!["hina", "rm", "-rf", "--verbose", "a"])).unwrap();
+    let flags = config.get_flags();
+    assert!(flags.parse_bool(vec!["r", "recursive"]));
+    assert!(flags.parse_bool(vec!["f", "force"]));
+    assert!(!flags.parse_bool(vec!["i"]));
+    assert!(flags.parse_bool(vec!["v", "verbose"]));
+    assert_eq!(config.get_args(), &vec![String::from("a")]);
+}
+
+#[test]
+fn parse_typed_flags() {
+    let specs = MODULE_MAP["rb"].get_flag_specs();
+    let input = to_args(vec!["-rs", "--to", "/tmp", "--last=2", "--on-conflict=rename", "--", "-ls"]);
+    let (flags, args) = Config::parse_flag_and_arg(&specs, "rb", &input).unwrap();
+    assert!(flags.contains_key("restore"));
+    assert_eq!(flags["to"], "/tmp");
+    assert_eq!(flags["last"], "2");
+    assert_eq!(args, vec![String::from("-ls")]);
+
+    // Values keep everything after the first '='
+    let specs = MODULE_MAP["rn"].get_flag_specs();
+    let (flags, _) = Config::parse_flag_and_arg(&specs, "rn", &to_args(vec!["-i=a=b"])).unwrap();
+    assert_eq!(flags["input"], "a=b");
+
+    // -h is human readable for ps and help everywhere else
+    let (flags, _) = Config::parse_flag_and_arg(&MODULE_MAP["ps"].get_flag_specs(), "ps", &to_args(vec!["-h"])).unwrap();
+    assert!(flags.contains_key("human-readable"));
+    let (flags, _) = Config::parse_flag_and_arg(&MODULE_MAP["rm"].get_flag_specs(), "rm", &to_args(vec!["-h"])).unwrap();
+    assert!(flags.contains_key("help"));
+
+    let specs = MODULE_MAP["rm"].get_flag_specs();
+    for bad in [vec!["--forse"], vec!["--passes=x"], vec!["--force=1"], vec!["--passes"], vec!["-fq"], vec!["--preserve-root=some"]] {
+        assert!(Config::parse_flag_and_arg(&specs, "rm", &to_args(bad)).is_err());
+    }
+}
+
+#[test]
+fn merge_config_defaults() {
+    let mut merged: Table = "[bin]\nquota = \"10G\"\nstore = \"dedup\"\n[flags]\nrm = [\"-v\", \"--preserve-root=all\"]".parse().unwrap();
+    merge_table(&mut merged, "[bin]\nquota = \"2G\"\n[output]\ncolor = \"never\"".parse().unwrap());
+    let settings = Settings::from(merged).unwrap();
+    assert_eq!(settings.bin.quota, "2G");
+    assert_eq!(settings.bin.store, "dedup");
+    assert_eq!(settings.output.color, "never");
+
+    // Flags given on the command line win over the defaults
+    let specs = MODULE_MAP["rm"].get_flag_specs();
+    let mut flags = HashMap::from([(String::from("preserve-root"), String::new())]);
+    Config::merge_default_flags(&specs, "rm", &mut flags, &settings.get_flags("rm")).unwrap();
+    assert_eq!(flags["preserve-root"], "");
+    assert!(flags.contains_key("verbose"));
+
+    assert!(Settings::from("[flags]\nfoo = [\"-v\"]".parse().unwrap()).is_err());
+    assert!(Settings::from("[bin]\nunknown = 1".parse().unwrap()).is_err());
+}
+
+#[test]
+fn help_subcommand() {
+    let config = Config::build(&to_args(vec!["hina", "help", "rb"])).unwrap();
+    assert_eq!(config.get_target().get_name(), "rb");
+    assert!(config.get_flags().parse_bool(vec!["help"]));
+    let config = Config::build(&to_args(vec!["hina", "help"])).unwrap();
+    assert_eq!(config.get_target().get_name(), "hina");
+    assert!(config.get_flags().parse_bool(vec!["help"]));
+}
+
+#[test]
+fn parse_optional_number_as_next_token() {
+    let specs = MODULE_MAP["rb"].get_flag_specs();
+    let (flags, args) = Config::parse_flag_and_arg(&specs, "rb", &to_args(vec!["--restore", "--last", "3"])).unwrap();
+    assert_eq!(flags["last"], "3");
+    assert!(args.is_empty());
+
+    // Anything but a number stays an argument
+    let (flags, args) = Config::parse_flag_and_arg(&specs, "rb", &to_args(vec!["--last", "3-5"])).unwrap();
+    assert_eq!(flags["last"], "");
+    assert_eq!(args, vec![String::from("3-5")]);
+    let (flags, _) = Config::parse_flag_and_arg(&specs, "rb", &to_args(vec!["--last", "--restore"])).unwrap();
+    assert_eq!(flags["last"], "");
+    assert!(flags.contains_key("restore"));
+}
+
+#[test]
+fn last_of_force_and_interactive_wins() {
+    let specs = MODULE_MAP["rm"].get_flag_specs();
+    let (flags, _) = Config::parse_flag_and_arg(&specs, "rm", &to_args(vec!["-i", "-f", "a"])).unwrap();
+    assert!(flags.contains_key("force"));
+    assert!(!flags.contains_key("i"));
+    let (flags, _) = Config::parse_flag_and_arg(&specs, "rm", &to_args(vec!["-f", "--interactive=once", "-vI"])).unwrap();
+    assert!(flags.contains_key("I"));
+    assert!(!flags.contains_key("force"));
+    assert!(!flags.contains_key("interactive"));
+
+    // A default -i does not make a -f on the command line prompt
+    let mut flags = HashMap::from([(String::from("force"), String::new())]);
+    Config::merge_default_flags(&specs, "rm", &mut flags, &to_args(vec!["-i", "-v"])).unwrap();
+    assert!(flags.contains_key("force"));
+    assert!(!flags.contains_key("i"));
+    assert!(flags.contains_key("verbose"));
+}
+
+#[test]
+fn completion_script_before_setup() {
+    let script = Completion::generate(&to_args(vec!["hina", "completions", "bash"])).unwrap().unwrap();
+    assert!(script.contains("complete -F"));
+    assert!(Completion::generate(&to_args(vec!["hina", "completions", "tcsh"])).unwrap().is_err());
+
+    // Listing records needs the data dir, so it goes through the executor as before
+    assert!(Completion::generate(&to_args(vec!["hina", "completions", "--list=records"])).is_none());
+    assert!(Completion::generate(&to_args(vec!["hina", "rm", "bash"])).is_none());
+}
@@ -0,0 +1,183 @@
+use std::{env, fs, process};
+use std::fs::File;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::PathBuf;
+
+use chrono::Duration;
+
+use hina::core::config::RMRecord;
+use hina::core::error::HinaError;
+use hina::core::func::{absolute_path, build_glob, glob_match, load_rm_stack, lock_data_dir, move_path, parse_duration, parse_index_range, parse_size, parse_time_point, save_rm_stack, shred_file, shred_path};
+use hina::core::global::{LOCK_FILE, RM_STACK};
+
+#[test]
+fn parse_index_and_range() {
+    assert_eq!(parse_index_range("3"), Some((3, 3)));
+    assert_eq!(parse_index_range("3-7"), Some((3, 7)));
+    assert_eq!(parse_index_range("7-3"), None);
+    assert_eq!(parse_index_range("a-b"), None);
+    assert_eq!(parse_index_range("/tmp/a"), None);
+}
+
+#[test]
+fn resolve_absolute_path() {
+    let work_path = PathBuf::from("/home/hina/work");
+    assert_eq!(absolute_path(&work_path, &PathBuf::from("./a")), PathBuf::from("/home/hina/work/a"));
+    assert_eq!(absolute_path(&work_path, &PathBuf::from("../b/./c")), PathBuf::from("/home/hina/b/c"));
+    assert_eq!(absolute_path(&work_path, &PathBuf::from("/etc/../var")), PathBuf::from("/var"));
+}
+
+#[test]
+fn match_glob_under_work_path() {
+    let work_path = PathBuf::from("/home/hina/work");
+    let pattern = build_glob(&work_path, "./*.log").unwrap();
+    assert!(glob_match(&pattern, "/home/hina/work/a.log"));
+    assert!(!glob_match(&pattern, "/home/hina/work/sub/a.log"));
+    assert!(!glob_match(&pattern, "/home/hina/a.log"));
+}
+
+#[test]
+fn parse_size_and_duration() {
+    assert_eq!(parse_size("512").unwrap(), 512);
+    assert_eq!(parse_size("100K").unwrap(), 100 * 1024);
+    assert_eq!(parse_size("1.5G").unwrap(), 3 * (1 << 29));
+    assert_eq!(parse_size("10GiB").unwrap(), 10 * (1 << 30));
+    assert!(parse_size("10X").is_err());
+    assert_eq!(parse_duration("30d").unwrap(), Duration::days(30));
+    assert_eq!(parse_duration("12h").unwrap(), Duration::hours(12));
+    assert!(parse_duration("d").is_err());
+    assert!(parse_duration("30日").is_err());
+    assert!(parse_duration("-1d").is_err());
+    assert!(parse_duration("99999999999999d").is_err());
+}
+
+#[test]
+fn parse_age_beyond_supported_dates() {
+    // A valid duration that still reaches before the earliest date chrono supports
+    assert!(parse_duration("100000000d").is_ok());
+    assert!(parse_time_point("100000000d").is_err());
+    assert!(parse_time_point("3d").unwrap() < parse_time_point("1d").unwrap());
+}
+
+#[test]
+fn shred_file_with_zeros() {
+    let path = env::temp_dir().join(format!("hina-shred-{}", process::id()));
+    fs::write(&path, b"top secret").unwrap();
+    // Read only like a chmod 400 key
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o400)).unwrap();
+    shred_file(&path, 2, true).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), vec![0u8; 10]);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn shred_path_past_failures() {
+    let root = env::temp_dir().join(format!("hina-shred-dir-{}", process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("locked")).unwrap();
+    fs::write(root.join("key"), "key").unwrap();
+    fs::set_permissions(root.join("key"), fs::Permissions::from_mode(0o400)).unwrap();
+    fs::write(root.join("locked/b"), "b").unwrap();
+    fs::set_permissions(root.join("locked"), fs::Permissions::from_mode(0o555)).unwrap();
+
+    // Unlinking from locked fails unless run as root, the key is shredded all the same
+    let (mut linked, mut failures) = (Vec::new(), Vec::new());
+    let result = shred_path(&root, 1, true, &mut linked, &mut failures);
+    assert!(fs::symlink_metadata(root.join("key")).is_err());
+    if fs::symlink_metadata(root.join("locked/b")).is_ok() {
+        assert!(result.is_err());
+        assert_eq!(failures.len(), 2);
+        assert_eq!(fs::read(root.join("locked/b")).unwrap(), vec![0u8; 1]);
+        fs::set_permissions(root.join("locked"), fs::Permissions::from_mode(0o755)).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+    } else {
+        assert!(result.is_ok());
+        assert!(failures.is_empty());
+        assert!(fs::symlink_metadata(&root).is_err());
+    }
+}
+
+#[test]
+fn save_and_load_rm_stack() {
+    let data_path = env::temp_dir().join(format!("hina-stack-{}", process::id()));
+    let _ = fs::remove_dir_all(&data_path);
+    fs::create_dir_all(&data_path).unwrap();
+    let rm_stack_path = data_path.join(RM_STACK);
+
+    // Saved through a temp file renamed over rm.stack, nothing else is left behind
+    let mut record = RMRecord::from(String::from("/bin/x"), String::from("/work/a b"), String::from("2026-01-01 00:00:00.000"), String::from("/bin"));
+    record.set_batch(String::from("batch"));
+    save_rm_stack(&data_path, &vec![record]).unwrap();
+    let rm_stack = load_rm_stack(&data_path).unwrap();
+    assert_eq!(rm_stack.len(), 1);
+    assert_eq!(rm_stack[0].get_src(), "/work/a b");
+    assert_eq!(rm_stack[0].get_batch(), "batch");
+    assert_eq!(fs::read_dir(&data_path).unwrap().count(), 1);
+
+    // A truncated stack is kept aside and reported instead of being read as empty
+    let content = fs::read_to_string(&rm_stack_path).unwrap();
+    fs::write(&rm_stack_path, &content[..content.len() / 2]).unwrap();
+    assert!(matches!(load_rm_stack(&data_path), Err(HinaError::StackCorruptError(_))));
+    let backups: Vec<PathBuf> = fs::read_dir(&data_path)
+        .unwrap()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.display().to_string().contains(".corrupt-"))
+        .collect();
+    assert_eq!(backups.len(), 1);
+    assert_eq!(fs::read_to_string(&backups[0]).unwrap(), &content[..content.len() / 2]);
+    fs::remove_dir_all(&data_path).unwrap();
+}
+
+#[test]
+fn lock_data_dir_once() {
+    let data_path = env::temp_dir().join(format!("hina-lock-{}", process::id()));
+    fs::create_dir_all(&data_path).unwrap();
+    let lock = lock_data_dir(&data_path).unwrap();
+    let other = File::open(data_path.join(LOCK_FILE)).unwrap();
+    assert!(other.try_lock().is_err());
+    drop(lock);
+    assert!(other.try_lock().is_ok());
+    fs::remove_dir_all(&data_path).unwrap();
+}
+
+#[test]
+fn move_path_across_filesystems() {
+    // /dev/shm is a tmpfs on most hosts, so moving there falls back to copy then remove
+    let other_fs = PathBuf::from("/dev/shm");
+    let src_root = env::temp_dir().join(format!("hina-exdev-{}", process::id()));
+    let dst_root = other_fs.join(format!("hina-exdev-{}", process::id()));
+    let same_fs = match (fs::metadata(env::temp_dir()), fs::metadata(&other_fs)) {
+        (Ok(tmp), Ok(shm)) => { tmp.dev() == shm.dev() }
+        _ => { true }
+    };
+    if same_fs {
+        return;
+    }
+    let _ = fs::remove_dir_all(&src_root);
+    let _ = fs::remove_dir_all(&dst_root);
+    fs::create_dir_all(src_root.join("dir/sub")).unwrap();
+    fs::create_dir_all(&dst_root).unwrap();
+    fs::write(src_root.join("dir/sub/a"), "a").unwrap();
+
+    move_path(&src_root.join("dir"), &dst_root.join("dir")).unwrap();
+    assert!(fs::symlink_metadata(src_root.join("dir")).is_err());
+    assert_eq!(fs::read_to_string(dst_root.join("dir/sub/a")).unwrap(), "a");
+
+    // The source can not be fully removed without write permission on sub, only root can,
+    // the copy is then kept as the only full one and reported apart
+    fs::create_dir_all(src_root.join("locked/sub")).unwrap();
+    fs::write(src_root.join("locked/sub/b"), "b").unwrap();
+    fs::set_permissions(src_root.join("locked/sub"), fs::Permissions::from_mode(0o555)).unwrap();
+    let result = move_path(&src_root.join("locked"), &dst_root.join("locked"));
+    if fs::symlink_metadata(src_root.join("locked/sub/b")).is_ok() {
+        assert!(matches!(result, Err(HinaError::PartialMoveError(_))));
+        assert_eq!(fs::read_to_string(dst_root.join("locked/sub/b")).unwrap(), "b");
+        fs::set_permissions(src_root.join("locked/sub"), fs::Permissions::from_mode(0o755)).unwrap();
+        fs::set_permissions(dst_root.join("locked/sub"), fs::Permissions::from_mode(0o755)).unwrap();
+    } else {
+        assert!(result.is_ok());
+    }
+    fs::remove_dir_all(&src_root).unwrap();
+    fs::remove_dir_all(&dst_root).unwrap();
+}
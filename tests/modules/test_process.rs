@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::fs;
+use std::process;
+
+use hina::event::process::{judge_leak, linear_fit, LeakVerdict, Process, ProcessInfo, ProcessMap, ProcessMemUsage};
+
+#[test]
+fn parse_ps_line_fallback() {
+    let proc_info = ProcessInfo::from("root        42     1  0 Oct17 ?        00:00:01 /usr/bin/app  --name  'a  b'").unwrap();
+    assert_eq!(proc_info.get_pid(), 42);
+    assert_eq!(proc_info.get_cmd(), "/usr/bin/app  --name  'a  b'");
+    assert!(ProcessInfo::from("UID PID PPID C STIME TTY TIME CMD").is_err());
+    assert!(ProcessInfo::from("root 42").is_err());
+}
+
+#[test]
+fn read_process_from_proc() {
+    let all_process = Process::get_all_process().unwrap();
+    let current = all_process.iter().find(|x| x.get_pid() == process::id() as usize).unwrap();
+    let arg0 = std::env::args().next().unwrap();
+    assert!(current.get_cmd().starts_with(&arg0));
+}
+
+#[test]
+fn build_map_list_keeps_last_mapping() {
+    let smaps = String::from("\
+55d0c0000000-55d0c0021000 rw-p 00000000 00:00 0                          [heap]
+Size:                132 kB
+Rss:                  12 kB
+Pss:                  12 kB
+Swap:                  0 kB
+7ffd1a000000-7ffd1a021000 rw-p 00000000 00:00 0                          [stack]
+Size:                132 kB
+Rss:                   8 kB
+Pss:                   8 kB
+Swap:                  4 kB
+");
+    let proc_map = Process::build_proc_map_list(&smaps, None).unwrap();
+    assert_eq!(proc_map.get_total("size"), 264);
+    assert_eq!(proc_map.get_total("rss"), 20);
+    assert_eq!(proc_map.get_total("swap"), 4);
+}
+
+#[test]
+fn total_of_missing_field_is_zero() {
+    // A hand copied smaps without Swap lines
+    let smaps = String::from("\
+55d0c0000000-55d0c0021000 rw-p 00000000 00:00 0                          [heap]
+Size:                132 kB
+Rss:                  12 kB
+");
+    let proc_map = Process::build_proc_map_list(&smaps, None).unwrap();
+    assert_eq!(proc_map.get_total_as_str("swap"), "0");
+    assert_eq!(proc_map.get_total_as_kb("swap"), "0 KB");
+    assert_eq!(proc_map.get_total_as_human_readable("pss"), "0.000 KB");
+    assert_eq!(proc_map.get_total_as_kb("size"), "132 KB");
+    assert!(ProcessMap::from(Vec::new()).is_err());
+}
+
+#[test]
+fn load_dump_skips_empty_smaps() {
+    let dump = std::env::temp_dir().join(format!("hina-test-dump-{}", process::id()));
+    let _ = fs::remove_dir_all(&dump);
+    fs::create_dir_all(dump.join("42")).unwrap();
+    fs::create_dir_all(dump.join("2")).unwrap();
+    fs::write(dump.join("42").join("smaps"), "\
+55d0c0000000-55d0c0021000 rw-p 00000000 00:00 0                          [heap]
+Size:                132 kB
+Rss:                  12 kB
+Pss:                  12 kB
+Swap:                  0 kB
+").unwrap();
+    fs::write(dump.join("42").join("cmdline"), "/usr/bin/app\0--name\0").unwrap();
+    fs::write(dump.join("2").join("smaps"), "").unwrap();
+    assert_eq!(Process::load_dump(&dump).unwrap().len(), 1);
+    fs::write(dump.join("42").join("smaps"), "not a mapping\n").unwrap();
+    assert!(Process::load_dump(&dump).is_err());
+    fs::remove_dir_all(&dump).unwrap();
+}
+
+#[test]
+fn fit_line_and_judge_leak() {
+    let (slope, r2) = linear_fit(&vec![(0.0, 1.0), (1.0, 3.0), (2.0, 5.0)]);
+    assert!((slope - 2.0).abs() < 1e-9 && (r2 - 1.0).abs() < 1e-9);
+    assert_eq!(linear_fit(&vec![(0.0, 4.0), (1.0, 4.0), (2.0, 4.0)]), (0.0, 0.0));
+    assert_eq!(linear_fit(&vec![(1.0, 1.0), (1.0, 9.0)]), (0.0, 0.0));
+    let (slope, r2) = linear_fit(&vec![(0.0, 0.0), (1.0, 10.0), (2.0, 0.0), (3.0, 12.0)]);
+    assert!(slope > 0.0 && r2 < 0.8);
+
+    assert_eq!(judge_leak(2, 1.0, 1.0, 1.0), LeakVerdict::TooFewSamples);
+    assert_eq!(judge_leak(3, 0.05, 1.0, 0.8), LeakVerdict::Likely);
+    assert_eq!(judge_leak(3, 0.05, 1.0, 0.79), LeakVerdict::Possible);
+    assert_eq!(judge_leak(3, 0.049, 1.0, 1.0), LeakVerdict::NoLeak);
+    assert_eq!(judge_leak(3, 0.5, 0.0, 1.0), LeakVerdict::NoLeak);
+}
+
+#[test]
+fn analyze_reused_pid_apart() {
+    // Times without zero padding are accepted by the parser, and pid 7 runs two commands
+    let input = std::env::temp_dir().join(format!("hina-test-analyze-{}.jsonl", process::id()));
+    fs::write(&input, "\
+{\"time\":\"2026-1-1 0:0:10.000\",\"pid\":7,\"cmd\":\"app\",\"total\":{\"pss\":100}}
+{\"time\":\"2026-1-1 0:0:0.000\",\"pid\":7,\"cmd\":\"app\",\"total\":{\"pss\":90}}
+{\"time\":\"2026-1-1 0:1:0.000\",\"pid\":7,\"cmd\":\"other\",\"total\":{\"pss\":500}}
+").unwrap();
+    let records = Process::load_mem_samples(&input).unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[&(7, String::from("app"))].len(), 2);
+    assert!(Process::analyze_proc_mem(&input, false).is_ok());
+    fs::remove_file(&input).unwrap();
+}
+
+#[test]
+fn watch_table_sort_delta_and_scroll() {
+    let user = String::from("root");
+    let usage = |pid: usize, cmd: &str, pss: u64| ProcessMemUsage::from(&user, pid, &String::from(cmd), [1000, 0, pss, pss * 2]);
+    let mut usages = vec![usage(1, "first", 100), usage(2, "second", 300), usage(3, "third", 200)];
+    let last = Process::track_mem_delta(&mut usages, &HashMap::new());
+
+    // Pid 1 grows, pid 3 is gone and pid 4 is new
+    let mut usages = vec![usage(1, "first", 400), usage(2, "second", 300), usage(4, "fourth", 50)];
+    Process::track_mem_delta(&mut usages, &last);
+    Process::sort_mem_usage(&mut usages, "pss");
+    let lines = Process::render_mem_usage(&usages, 0, false, 10, 200);
+    assert_eq!(lines.len(), 4);
+    assert!(lines[1].contains("+300 KB") && lines[1].contains("first"));
+    assert!(lines[2].contains("second") && !lines[2].contains("+"));
+    assert!(lines[3].contains("fourth") && lines[3].contains(" -  "));
+
+    // Two rows fit, the third selected row scrolls the first out
+    let lines = Process::render_mem_usage(&usages, 2, false, 3, 200);
+    assert_eq!(lines.len(), 3);
+    assert!(lines[1].contains("second") && lines[2].contains("fourth"));
+    // A terminal of a single row leaves room for no table rows
+    assert_eq!(Process::render_mem_usage(&usages, 0, false, 0, 200).len(), 1);
+    Process::sort_mem_usage(&mut usages, "pid");
+    assert!(Process::render_mem_usage(&usages, 0, false, 10, 200)[3].contains("fourth"));
+}
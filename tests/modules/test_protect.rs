@@ -0,0 +1,59 @@
+use std::{env, fs, process};
+use std::path::{Path, PathBuf};
+
+use hina::core::global::PROTECT_FILE;
+use hina::core::protect::{check, load_rules, ProtectAction, ProtectRule};
+
+#[test]
+fn match_protect_rules() {
+    let home = PathBuf::from("/nonexistent/home/hina");
+    let literal = ProtectRule::from("deny $HOME/.hina", &home).unwrap();
+    assert!(literal.matches(Path::new("/nonexistent/home/hina/.hina")));
+    // Removing an ancestor would remove the protected path as well
+    assert!(literal.matches(Path::new("/nonexistent/home")));
+    assert!(!literal.matches(Path::new("/nonexistent/home/hina/.hina/RecycleBin")));
+
+    let name = ProtectRule::from("confirm .git", &home).unwrap();
+    assert!(name.matches(Path::new("/src/repo/.git")));
+    assert!(!name.matches(Path::new("/src/repo/.github")));
+
+    let glob = ProtectRule::from("deny **/.hina-trash/**", &home).unwrap();
+    assert!(glob.matches(Path::new("/mnt/disk/.hina-trash/1000")));
+    assert!(ProtectRule::from("deny relative/path", &home).is_err());
+}
+
+#[test]
+fn protect_subtree_with_glob() {
+    // A plain path leaves what is inside removable, like the default deny / and deny $HOME
+    let home = PathBuf::from("/nonexistent/home/hina");
+    let literal = ProtectRule::from("deny /srv/data", &home).unwrap();
+    assert!(!literal.matches(Path::new("/srv/data/db")));
+    let subtree = ProtectRule::from("deny /srv/data/**", &home).unwrap();
+    assert!(subtree.matches(Path::new("/srv/data/db")));
+    assert!(subtree.matches(Path::new("/srv/data/db/table")));
+    assert!(!subtree.matches(Path::new("/srv/database")));
+
+    let name = ProtectRule::from("confirm .git", &home).unwrap();
+    assert!(!name.matches(Path::new("/src/repo/.git/config")));
+    let inside = ProtectRule::from("confirm **/.git/**", &home).unwrap();
+    assert!(inside.matches(Path::new("/src/repo/.git/config")));
+}
+
+#[test]
+fn guard_bins_over_protect_file() {
+    let data_path = env::temp_dir().join(format!("hina-protect-{}", process::id()));
+    let bin = PathBuf::from("/nonexistent/data/bin");
+    fs::create_dir_all(&data_path).unwrap();
+    fs::write(data_path.join(PROTECT_FILE), "confirm /srv/data/**\n").unwrap();
+    let rules = load_rules(&data_path, &[bin.clone()]).unwrap();
+
+    // The protect file adds to the defaults
+    assert_eq!(check(&rules, Path::new("/")).unwrap().get_action(), ProtectAction::Deny);
+    assert_eq!(check(&rules, Path::new("/srv/data/db")).unwrap().get_action(), ProtectAction::Confirm);
+    // The bin, its ancestors and what is inside are denied
+    for path in ["/nonexistent/data/bin", "/nonexistent/data", "/nonexistent/data/bin/x/y"] {
+        assert_eq!(check(&rules, Path::new(path)).unwrap().get_action(), ProtectAction::Deny);
+    }
+    assert!(check(&rules, Path::new("/nonexistent/data/other")).is_none());
+    fs::remove_dir_all(&data_path).unwrap();
+}
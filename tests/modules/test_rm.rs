@@ -1,8 +1,257 @@
+use std::{env, fs, process};
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+use hina::core::config::{Config, RMRecord};
+use hina::core::error::HinaError;
+use hina::core::func::get_uid;
+use hina::core::global::RECYCLE;
+use hina::event::base::HinaModuleRun;
+use hina::event::recycle::{RecycleBin, Remove};
+
 use crate::modules::consts::TEST_ITER;
 
+// Work dir and data dir of one test under the temp dir, removed when dropped, the root
+// is HOME when the binary runs
+pub struct Sandbox {
+    pub root: PathBuf,
+    pub work: PathBuf,
+    pub data: PathBuf,
+    pub bin: PathBuf,
+    pub uid: String,
+}
+
+impl Sandbox {
+    pub fn new(name: &str) -> Sandbox {
+        let root = env::temp_dir().join(format!("hina-test-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let work = root.join("work");
+        let data = root.join("data");
+        let bin = data.join(RECYCLE);
+        fs::create_dir_all(&work).unwrap();
+        fs::create_dir_all(&bin).unwrap();
+        Sandbox { root, work, data, bin, uid: get_uid().unwrap() }
+    }
+
+    pub fn rb(&self, input: Vec<&str>, rm_stack: &mut Vec<RMRecord>) {
+        let mut argv = vec![String::from("hina"), String::from("rb")];
+        argv.extend(input.into_iter().map(String::from));
+        let config = Config::build(&argv).unwrap();
+        let (user, flags, args) = (String::from("hina"), config.get_flags(), config.get_args());
+        if !RecycleBin.run_batch(&self.work, &self.data, &self.bin, &user, &self.uid, flags, rm_stack, args).unwrap() {
+            RecycleBin.run(&self.work, &self.data, &self.bin, &user, &self.uid, flags, rm_stack, args.first()).unwrap();
+        }
+    }
+
+    pub fn rm(&self, input: Vec<&str>, rm_stack: &mut Vec<RMRecord>) -> Result<bool, HinaError> {
+        let mut argv = vec![String::from("hina"), String::from("rm")];
+        argv.extend(input.into_iter().map(String::from));
+        let config = Config::build(&argv).unwrap();
+        let (user, flags, args) = (String::from("hina"), config.get_flags(), config.get_args());
+        Remove.run_batch(&self.work, &self.data, &self.bin, &user, &self.uid, flags, rm_stack, args)
+    }
+
+    pub fn hina(&self, input: Vec<&str>, envs: Vec<(&str, &str)>) -> Output {
+        // Run the binary with the sandbox root as HOME, for what takes separate invocations
+        let mut command = Command::new(env!("CARGO_BIN_EXE_hina"));
+        for (key, _) in env::vars().filter(|(key, _)| key.starts_with("HINA_") || key.starts_with("XDG_")) {
+            command.env_remove(key);
+        }
+        command
+            .args(input)
+            .current_dir(&self.work)
+            .env("HOME", &self.root)
+            .env("USER", "hina")
+            .env_remove("DEBUG")
+            .envs(envs)
+            .stdin(Stdio::null())
+            .output()
+            .unwrap()
+    }
+
+    pub fn list(&self, envs: Vec<(&str, &str)>) -> Vec<serde_json::Value> {
+        let output = self.hina(vec!["rb", "--list", "--format=json"], envs);
+        assert!(output.status.success(), "{:?}", output);
+        serde_json::from_slice(&output.stdout).unwrap()
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
 #[test]
 fn generate_random_files() {
     for _ in 0..TEST_ITER {
         assert_eq!(4, 4);
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn restore_mode_000_file() {
+    // As non-root the mtime of a mode 000 file can not be set, the file is still restored
+    let sandbox = Sandbox::new("restore-000");
+    let target = sandbox.work.join("locked");
+    fs::write(&target, b"locked").unwrap();
+    fs::set_permissions(&target, fs::Permissions::from_mode(0o000)).unwrap();
+    let mut rm_stack = Vec::new();
+    Remove::remove_to_bin(&target, &sandbox.data, &sandbox.bin, &sandbox.uid, &mut rm_stack).unwrap();
+    assert!(fs::symlink_metadata(&target).is_err());
+
+    sandbox.rb(vec!["--restore", "0"], &mut rm_stack);
+    assert!(rm_stack.is_empty());
+    assert_eq!(fs::symlink_metadata(&target).unwrap().permissions().mode() & 0o7777, 0o000);
+}
+
+#[test]
+fn restore_range_out_of_bin() {
+    let sandbox = Sandbox::new("restore-range");
+    let mut rm_stack = Vec::new();
+    for name in ["a", "b"] {
+        let target = sandbox.work.join(name);
+        fs::write(&target, name).unwrap();
+        Remove::remove_to_bin(&target, &sandbox.data, &sandbox.bin, &sandbox.uid, &mut rm_stack).unwrap();
+    }
+    // Reported without trying to build the whole range
+    sandbox.rb(vec!["--restore", "0-99999999999"], &mut rm_stack);
+    sandbox.rb(vec!["--restore", "1-2"], &mut rm_stack);
+    assert_eq!(rm_stack.len(), 2);
+    sandbox.rb(vec!["--restore", "0-1"], &mut rm_stack);
+    assert!(rm_stack.is_empty());
+    assert_eq!(fs::read_to_string(sandbox.work.join("b")).unwrap(), "b");
+}
+
+#[test]
+fn xdg_trash_round_trip() {
+    let sandbox = Sandbox::new("xdg");
+    let xdg = vec![("HINA_TRASH_BACKEND", "xdg")];
+    let trash = sandbox.root.join(".local/share/Trash");
+    let name = "a b%c.txt";
+    fs::write(sandbox.work.join(name), "a").unwrap();
+    fs::write(sandbox.work.join("kept"), "kept").unwrap();
+    assert!(sandbox.hina(vec!["rm", name, "kept"], xdg.clone()).status.success());
+
+    // Written as the spec says, with the path percent encoded
+    let info = fs::read_to_string(trash.join(format!("info/{}.trashinfo", name))).unwrap();
+    let encoded = format!("Path={}/a%20b%25c.txt\n", sandbox.work.display());
+    assert!(info.starts_with("[Trash Info]\n"), "{}", info);
+    assert!(info.contains(&encoded), "{}", info);
+    assert_eq!(fs::read_to_string(trash.join("files").join(name)).unwrap(), "a");
+
+    // An entry trashed by another tool, with an encoded path
+    fs::write(trash.join("files/other"), "other").unwrap();
+    let other_info = format!("[Trash Info]\nPath={}/x%20y\nDeletionDate=2026-01-02T03:04:05\n", sandbox.work.display());
+    fs::write(trash.join("info/other.trashinfo"), other_info).unwrap();
+    let entries = sandbox.list(xdg.clone());
+    let srcs: Vec<&str> = entries.iter().map(|entry| entry["src"].as_str().unwrap()).collect();
+    assert_eq!(entries.len(), 3, "{:?}", srcs);
+    assert!(srcs.contains(&sandbox.work.join("x y").to_str().unwrap()));
+    assert!(srcs.contains(&sandbox.work.join(name).to_str().unwrap()));
+
+    // Restoring drops the .trashinfo of what was restored only
+    let output = sandbox.hina(vec!["rb", "--restore", name, "x y"], xdg.clone());
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(fs::read_to_string(sandbox.work.join(name)).unwrap(), "a");
+    assert_eq!(fs::read_to_string(sandbox.work.join("x y")).unwrap(), "other");
+    let infos: Vec<String> = fs::read_dir(trash.join("info")).unwrap().flatten().map(|entry| entry.file_name().to_string_lossy().to_string()).collect();
+    assert_eq!(infos, vec![String::from("kept.trashinfo")]);
+    assert_eq!(sandbox.list(xdg).len(), 1);
+}
+
+#[test]
+fn restore_on_conflict() {
+    let sandbox = Sandbox::new("conflict");
+    let target = sandbox.work.join("a.txt");
+    let mut rm_stack = Vec::new();
+    fs::write(&target, "old").unwrap();
+    Remove::remove_to_bin(&target, &sandbox.data, &sandbox.bin, &sandbox.uid, &mut rm_stack).unwrap();
+    fs::write(&target, "new").unwrap();
+
+    // Skipped by default, the entry stays in the bin
+    sandbox.rb(vec!["--restore", "0"], &mut rm_stack);
+    assert_eq!(rm_stack.len(), 1);
+    assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+
+    sandbox.rb(vec!["--restore", "0", "--on-conflict=rename"], &mut rm_stack);
+    assert!(rm_stack.is_empty());
+    assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+    assert_eq!(fs::read_to_string(sandbox.work.join("a.txt.1")).unwrap(), "old");
+
+    // The overwritten file goes to the bin in turn
+    Remove::remove_to_bin(&target, &sandbox.data, &sandbox.bin, &sandbox.uid, &mut rm_stack).unwrap();
+    fs::write(&target, "newer").unwrap();
+    sandbox.rb(vec!["--restore", "0", "--on-conflict=overwrite"], &mut rm_stack);
+    assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+    assert_eq!(rm_stack.len(), 1);
+    assert_eq!(rm_stack[0].get_src(), target.to_str().unwrap());
+
+    // Into another dir, created when missing
+    sandbox.rb(vec!["--restore", "0", "--to=sub/dir"], &mut rm_stack);
+    assert!(rm_stack.is_empty());
+    assert_eq!(fs::read_to_string(sandbox.work.join("sub/dir/a.txt")).unwrap(), "newer");
+}
+
+#[test]
+fn atomic_remove_rolls_back() {
+    let sandbox = Sandbox::new("atomic");
+    for name in ["a", "b"] {
+        fs::write(sandbox.work.join(name), name).unwrap();
+    }
+    let mut rm_stack = Vec::new();
+
+    // a is moved back once missing fails, and b is never touched
+    let result = sandbox.rm(vec!["--atomic", "a", "missing", "b"], &mut rm_stack);
+    assert!(matches!(result, Err(HinaError::BatchError(_))));
+    assert!(rm_stack.is_empty());
+    assert_eq!(fs::read_to_string(sandbox.work.join("a")).unwrap(), "a");
+    assert_eq!(fs::read_to_string(sandbox.work.join("b")).unwrap(), "b");
+    assert_eq!(fs::read_dir(&sandbox.bin).unwrap().count(), 0);
+
+    // Without --atomic the others are still removed and recorded
+    let result = sandbox.rm(vec!["a", "missing", "b"], &mut rm_stack);
+    assert!(matches!(result, Err(HinaError::BatchError(_))));
+    assert_eq!(rm_stack.len(), 2);
+    assert!(fs::symlink_metadata(sandbox.work.join("a")).is_err());
+    assert!(fs::symlink_metadata(sandbox.work.join("b")).is_err());
+}
+
+#[test]
+fn undo_last_and_given_batch() {
+    let sandbox = Sandbox::new("undo");
+    for name in ["a", "b", "c"] {
+        fs::write(sandbox.work.join(name), name).unwrap();
+    }
+    assert!(sandbox.hina(vec!["rm", "a", "b"], vec![]).status.success());
+    assert!(sandbox.hina(vec!["rm", "c"], vec![]).status.success());
+    let entries = sandbox.list(vec![]);
+    let batch_of = |name: &str| {
+        let src = sandbox.work.join(name).display().to_string();
+        entries.iter().find(|entry| entry["src"] == src.as_str()).unwrap()["batch"].as_str().unwrap().to_string()
+    };
+    assert_eq!(batch_of("a"), batch_of("b"));
+    assert_ne!(batch_of("a"), batch_of("c"));
+    // The deletion time is kept to the millisecond
+    assert_eq!(entries[0]["delete_time"].as_str().unwrap().len(), "2026-01-01 00:00:00.000".len());
+
+    // Only the last rm is undone
+    assert!(sandbox.hina(vec!["rb", "--undo"], vec![]).status.success());
+    assert!(sandbox.work.join("c").exists());
+    assert!(!sandbox.work.join("a").exists());
+    assert_eq!(sandbox.list(vec![]).len(), 2);
+
+    // An older batch by its id, listed on its own first
+    assert!(sandbox.hina(vec!["rm", "c"], vec![]).status.success());
+    let batch = batch_of("a");
+    let output = sandbox.hina(vec!["rb", "--list", "--format=json", &format!("--batch={}", batch)], vec![]);
+    let listed: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(listed.len(), 2);
+    assert!(sandbox.hina(vec!["rb", "--undo", &format!("--batch={}", batch)], vec![]).status.success());
+    assert_eq!(fs::read_to_string(sandbox.work.join("a")).unwrap(), "a");
+    assert_eq!(fs::read_to_string(sandbox.work.join("b")).unwrap(), "b");
+    assert!(!sandbox.work.join("c").exists());
+    assert_eq!(sandbox.list(vec![]).len(), 1);
+    assert!(!sandbox.hina(vec!["rb", "--undo", "--batch=unknown"], vec![]).status.success());
+}
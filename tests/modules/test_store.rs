@@ -0,0 +1,139 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+
+use chrono::Local;
+
+use hina::core::config::{Compression, RMRecord};
+use hina::core::global::TIME_FORMAT;
+use hina::core::store;
+
+use crate::modules::test_rm::Sandbox;
+
+fn remove_to_store(sandbox: &Sandbox, name: &str, content: &str, rm_stack: &mut Vec<RMRecord>) -> PathBuf {
+    // What rm does with a regular file when bin.store is dedup
+    let target = sandbox.work.join(name);
+    fs::write(&target, content).unwrap();
+    let meta = fs::symlink_metadata(&target).unwrap();
+    let store_path = store::get_store_path(&sandbox.data);
+    let hash = store::put(&store_path, &target).unwrap();
+    let mut record = RMRecord::from(store::blob_path(&store_path, &hash).display().to_string(),
+                                    target.display().to_string(),
+                                    Local::now().format(TIME_FORMAT).to_string(),
+                                    store_path.display().to_string());
+    record.set_meta(&meta, meta.len(), None);
+    record.set_blob(Some(hash));
+    rm_stack.push(record);
+    target
+}
+
+fn blob_count(sandbox: &Sandbox) -> usize {
+    let store_path = store::get_store_path(&sandbox.data);
+    match fs::read_dir(&store_path) {
+        Ok(dirs) => { dirs.flatten().map(|dir| fs::read_dir(dir.path()).unwrap().count()).sum() }
+        Err(_) => { 0 }
+    }
+}
+
+#[test]
+fn put_identical_files_once() {
+    let sandbox = Sandbox::new("store-put");
+    let mut rm_stack = Vec::new();
+    let a = remove_to_store(&sandbox, "a", "same", &mut rm_stack);
+    let b = remove_to_store(&sandbox, "b", "same", &mut rm_stack);
+    remove_to_store(&sandbox, "c", "other", &mut rm_stack);
+    assert!(!a.exists() && !b.exists());
+    assert_eq!(rm_stack[0].get_blob(), rm_stack[1].get_blob());
+    assert_eq!(blob_count(&sandbox), 2);
+}
+
+#[test]
+fn restore_shared_blob() {
+    let sandbox = Sandbox::new("store-restore");
+    let mut rm_stack = Vec::new();
+    let a = remove_to_store(&sandbox, "a", "same", &mut rm_stack);
+    let b = remove_to_store(&sandbox, "b", "same", &mut rm_stack);
+
+    // The blob is copied out while b still refers to it, and moved out with the last record
+    sandbox.rb(vec!["--restore", "0"], &mut rm_stack);
+    assert_eq!(fs::read_to_string(&a).unwrap(), "same");
+    assert_eq!(blob_count(&sandbox), 1);
+    sandbox.rb(vec!["--restore", "0"], &mut rm_stack);
+    assert_eq!(fs::read_to_string(&b).unwrap(), "same");
+    assert!(rm_stack.is_empty());
+    assert_eq!(blob_count(&sandbox), 0);
+}
+
+#[test]
+fn purge_one_of_shared_blob() {
+    let sandbox = Sandbox::new("store-purge");
+    let mut rm_stack = Vec::new();
+    let a = remove_to_store(&sandbox, "a", "same", &mut rm_stack);
+    let b = remove_to_store(&sandbox, "b", "same", &mut rm_stack);
+
+    sandbox.rb(vec!["--purge", "--match", &a.display().to_string()], &mut rm_stack);
+    assert_eq!(rm_stack.len(), 1);
+    assert_eq!(blob_count(&sandbox), 1);
+    sandbox.rb(vec!["--restore", "0"], &mut rm_stack);
+    assert_eq!(fs::read_to_string(&b).unwrap(), "same");
+    assert!(!a.exists());
+    assert_eq!(blob_count(&sandbox), 0);
+}
+
+#[test]
+fn compress_and_take_back() {
+    let sandbox = Sandbox::new("store-compress");
+    let store_path = store::get_store_path(&sandbox.data);
+    let content = "hina ".repeat(1000);
+    for compression in [Compression::Zstd, Compression::Gzip] {
+        let target = sandbox.work.join("log");
+        fs::write(&target, &content).unwrap();
+        let hash = store::put(&store_path, &target).unwrap();
+        store::compress(&store_path, &hash, compression).unwrap();
+        let (blob, stored) = store::find_blob(&store_path, &hash).unwrap();
+        assert_eq!(stored, compression);
+        assert!(fs::metadata(&blob).unwrap().len() < content.len() as u64);
+
+        // A shared take keeps the blob, the last one removes it
+        store::take(&store_path, &hash, &target, true).unwrap();
+        assert_eq!(fs::read_to_string(&target).unwrap(), content);
+        fs::remove_file(&target).unwrap();
+        store::take(&store_path, &hash, &target, false).unwrap();
+        assert_eq!(fs::read_to_string(&target).unwrap(), content);
+        assert!(store::find_blob(&store_path, &hash).is_none());
+        fs::remove_file(&target).unwrap();
+    }
+}
+
+#[test]
+fn empty_store_blobs() {
+    let sandbox = Sandbox::new("store-empty");
+    let mut rm_stack = Vec::new();
+    remove_to_store(&sandbox, "a", "same", &mut rm_stack);
+    remove_to_store(&sandbox, "b", "same", &mut rm_stack);
+    remove_to_store(&sandbox, "c", "other", &mut rm_stack);
+    assert_eq!(blob_count(&sandbox), 2);
+
+    // The blobs are removed and so are the directories that held them
+    sandbox.rb(vec!["--empty"], &mut rm_stack);
+    assert!(rm_stack.is_empty());
+    assert_eq!(fs::read_dir(store::get_store_path(&sandbox.data)).unwrap().count(), 0);
+}
+
+#[test]
+fn find_orphan_blobs() {
+    let sandbox = Sandbox::new("store-orphan");
+    let mut rm_stack = Vec::new();
+    remove_to_store(&sandbox, "a", "kept", &mut rm_stack);
+    remove_to_store(&sandbox, "b", "compressed", &mut rm_stack);
+    // Like a crash between put and saving the record
+    remove_to_store(&sandbox, "c", "lost", &mut rm_stack);
+    let lost = rm_stack.pop().unwrap();
+    let store_path = store::get_store_path(&sandbox.data);
+    store::compress(&store_path, rm_stack[1].get_blob().as_ref().unwrap(), Compression::Zstd).unwrap();
+
+    let referenced: HashSet<String> = rm_stack.iter().filter_map(|record| record.get_blob().clone()).collect();
+    let orphans = store::find_orphans(&store_path, &referenced).unwrap();
+    assert_eq!(orphans, vec![PathBuf::from(lost.get_file())]);
+    assert!(store::find_orphans(&sandbox.data.join("nonexistent"), &referenced).unwrap().is_empty());
+}